@@ -0,0 +1,89 @@
+use anyhow::bail;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Names that are taken by other routes.
+const RESERVED_NAMES: [&str; 6] = ["admin", "api", "f", "fp", "health", "metrics"];
+const MAX_NAME_LENGTH: usize = 64;
+
+/// A vanity name for a project, file or rule, e.g. `mods.cf/sparkweave`.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Alias {
+    pub name: String,
+    pub target: AliasTarget,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum AliasTarget {
+    Project {
+        project_id: u64,
+    },
+    File {
+        file_id: u64,
+    },
+    /// The newest file of a project for the given game version and, optionally, mod loader.
+    LatestFile {
+        project_id: u64,
+        game_version: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        mod_loader: Option<String>,
+    },
+}
+
+impl AliasTarget {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            AliasTarget::Project { project_id: 0 }
+            | AliasTarget::File { file_id: 0 }
+            | AliasTarget::LatestFile { project_id: 0, .. } => bail!("IDs must be greater than 0"),
+            AliasTarget::LatestFile { game_version, .. } if game_version.trim().is_empty() => {
+                bail!("game_version must not be empty")
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Checks that a name can be used as an alias without colliding with numeric project IDs or other
+/// routes.
+pub(crate) fn validate_name(name: &str) -> anyhow::Result<()> {
+    if name.is_empty() || name.len() > MAX_NAME_LENGTH {
+        bail!("Alias names must be between 1 and {MAX_NAME_LENGTH} characters long");
+    }
+    if !name
+        .bytes()
+        .all(|it| it.is_ascii_lowercase() || it.is_ascii_digit() || b"._-".contains(&it))
+    {
+        bail!("Alias names may only contain lowercase letters, digits, '.', '_' and '-'");
+    }
+    if !name.starts_with(|it: char| it.is_ascii_alphanumeric()) {
+        bail!("Alias names must start with a letter or digit");
+    }
+    if name.bytes().all(|it| it.is_ascii_digit()) {
+        bail!("Alias names must not be numeric, as those are project IDs");
+    }
+    if RESERVED_NAMES.contains(&name) {
+        bail!("Alias name '{name}' is reserved");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::aliases::validate_name;
+
+    #[test]
+    fn should_validate_names() {
+        assert!(validate_name("sparkweave").is_ok());
+        assert!(validate_name("sw-1.20").is_ok());
+        assert!(validate_name("911456").is_err());
+        assert!(validate_name("Sparkweave").is_err());
+        assert!(validate_name("-sw").is_err());
+        assert!(validate_name("health").is_err());
+        assert!(validate_name("").is_err());
+    }
+}
@@ -1,66 +1,295 @@
-use crate::util::{CaptureEventProperties, StatusExt};
+use crate::analytics::json_lines::JsonLinesSink;
+use crate::analytics::plausible::PlausibleSink;
+use crate::analytics::posthog::PostHogSink;
+use crate::analytics::sqlite::SqliteSink;
+use crate::analytics::stdout::StdoutSink;
+use crate::config::{AnalyticsConfig, BotHandling, PrivacyConfig};
+use crate::hosts::HostSettings;
+use crate::metrics::Metrics;
+use crate::resolve::{ResolutionContext, SharedResolution};
+use crate::util::{CheckStatus, HealthCheck, StatusExt};
 use crate::web::AppState;
-use anyhow::{Context, anyhow};
+use async_trait::async_trait;
 use axum::extract::{Request, State};
 use axum::http::StatusCode;
-use axum::http::header::USER_AGENT;
+use axum::http::header::{REFERER, USER_AGENT};
 use axum::middleware::Next;
 use axum::response::Response;
-use posthog_rs::{Client, ClientOptionsBuilder, Event};
-use std::env;
-use std::sync::Arc;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::{mpsc, oneshot};
+use tokio::task::JoinHandle;
+use url::Url;
 
+mod json_lines;
+mod plausible;
+mod posthog;
+pub(crate) mod privacy;
+mod sqlite;
+mod stdout;
+
+/// How long shutting down waits for queued events to be captured.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// What a captured event stands for.
+#[derive(Serialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EventKind {
+    PageView,
+    /// Only the error of a failed request, reported even if the visitor opted out or is a dropped
+    /// bot. It carries nothing that identifies the visitor and is not a page view.
+    Exception,
+}
+
+impl EventKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            EventKind::PageView => "page_view",
+            EventKind::Exception => "exception",
+        }
+    }
+}
+
+/// A page view, or just the error of a request that isn't reported as one, as reported to every
+/// configured sink.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct PageView {
+    pub event: EventKind,
+    pub timestamp: DateTime<Utc>,
+    pub url: Url,
+    /// Host name reported to analytics, which may differ from the URL's.
+    pub host: String,
+    pub path: String,
+    pub status: u16,
+    pub success: bool,
+    pub user_agent: Option<String>,
+    /// Identifies a visitor for the rest of the day, if visitor hashes are enabled.
+    pub visitor_id: Option<String>,
+    /// Whether the user agent belongs to a crawler, link preview bot or script.
+    pub bot: bool,
+    /// Host of the `Referer`, without path or query.
+    pub referrer_host: Option<String>,
+    #[serde(flatten)]
+    pub resolution: ResolutionContext,
+    /// Truncated to a network prefix and only forwarded to sinks that need it to tell visitors
+    /// apart, never stored.
+    #[serde(skip)]
+    pub client_ip: IpAddr,
+}
+
+/// A destination for page views.
+#[async_trait]
+pub(crate) trait AnalyticsSink: Send + Sync {
+    /// Identifies the sink in metrics, logs and health checks.
+    fn name(&self) -> &'static str;
+
+    async fn capture(&self, events: &[PageView]) -> anyhow::Result<()>;
+}
+
+/// Fans out page views to all configured sinks.
 #[derive(Default)]
 pub(crate) struct Analytics {
-    client: Option<Client>,
+    sinks: Vec<Box<dyn AnalyticsSink>>,
+    privacy: PrivacyConfig,
+    /// The last error of every sink whose last capture failed.
+    failures: Mutex<BTreeMap<&'static str, (DateTime<Utc>, String)>>,
 }
 
 impl Analytics {
-    pub async fn capture(&self, event: Event) -> anyhow::Result<()> {
-        if let Some(client) = &self.client {
-            client.capture(event).await.map_err(|err| anyhow!(err))?;
+    pub fn is_enabled(&self) -> bool {
+        !self.sinks.is_empty()
+    }
+
+    pub fn privacy(&self) -> &PrivacyConfig {
+        &self.privacy
+    }
+
+    /// Captures the events with every sink, returning the names of the sinks that failed.
+    pub async fn capture_batch(&self, events: &[PageView]) -> Vec<&'static str> {
+        let mut failed = Vec::new();
+        for sink in &self.sinks {
+            let result = sink.capture(events).await;
+            let mut failures = self.failures.lock().expect("analytics state poisoned");
+            match result {
+                Ok(()) => {
+                    failures.remove(sink.name());
+                }
+                Err(err) => {
+                    tracing::error!(
+                        "Unable to capture {count} events with {sink}: {err:#}",
+                        count = events.len(),
+                        sink = sink.name()
+                    );
+                    failures.insert(sink.name(), (Utc::now(), format!("{err:#}")));
+                    failed.push(sink.name());
+                }
+            }
         }
 
-        Ok(())
+        failed
+    }
+
+    pub fn health_check(&self) -> HealthCheck {
+        if self.sinks.is_empty() {
+            return HealthCheck::new(CheckStatus::Disabled, false);
+        }
+
+        let failures = self.failures.lock().expect("analytics state poisoned");
+        match failures.values().map(|(time, _)| *time).max() {
+            None => HealthCheck::new(CheckStatus::Ok, false),
+            Some(time) => HealthCheck::new(CheckStatus::Degraded, false)
+                .with_message(
+                    failures
+                        .iter()
+                        .map(|(sink, (_, err))| format!("Last capture with {sink} failed: {err}"))
+                        .collect::<Vec<_>>()
+                        .join("; "),
+                )
+                .checked_at(time),
+        }
     }
 }
 
-pub(crate) async fn init(enable: bool) -> anyhow::Result<Analytics> {
-    if !enable {
-        return Ok(Analytics::default());
+/// Bounded queue of events waiting to be captured by the [`AnalyticsWorker`], so that requests never
+/// wait for a sink.
+pub(crate) struct AnalyticsQueue {
+    sender: mpsc::Sender<PageView>,
+    metrics: Arc<Metrics>,
+}
+
+impl AnalyticsQueue {
+    pub fn new(
+        config: &AnalyticsConfig,
+        metrics: Arc<Metrics>,
+    ) -> (Self, mpsc::Receiver<PageView>) {
+        let (sender, receiver) = mpsc::channel(config.queue_capacity);
+        (AnalyticsQueue { sender, metrics }, receiver)
     }
 
-    let Some(posthog_url) = env::var("POSTHOG_INSTANCE_URL").ok() else {
-        return Ok(Analytics::default());
-    };
+    /// Enqueues an event without waiting, dropping it if the queue is full.
+    pub fn push(&self, event: PageView) {
+        match self.sender.try_send(event) {
+            Ok(()) => self.metrics.analytics_queue_depth.inc(),
+            Err(TrySendError::Full(_)) => {
+                self.discard("queue_full");
+                tracing::debug!("Analytics queue is full, dropping event");
+            }
+            Err(TrySendError::Closed(_)) => self.discard("shutdown"),
+        }
+    }
 
-    let posthog_project_api_key = env::var("POSTHOG_PROJECT_API_KEY")
-        .context("PostHog analytics are enabled but no POSTHOG_PROJECT_API_KEY was provided!")?;
+    /// Counts an event that was dropped before being enqueued.
+    pub fn discard(&self, reason: &str) {
+        self.metrics
+            .analytics_dropped
+            .with_label_values(&[reason])
+            .inc();
+    }
+}
 
-    // TODO posthog sdk does not support error tracking yet :/
-    let posthog_personal_api_key = env::var("POSTHOG_PERSONAL_API_KEY").ok();
+/// Background task that drains the [`AnalyticsQueue`] in batches.
+pub(crate) struct AnalyticsWorker {
+    stop: oneshot::Sender<()>,
+    handle: JoinHandle<()>,
+}
 
-    let options = ClientOptionsBuilder::default()
-        .host(posthog_url)
-        .api_key(posthog_project_api_key)
-        .personal_api_key(posthog_personal_api_key.unwrap_or_default())
-        .build()?;
+impl AnalyticsWorker {
+    pub fn spawn(
+        state: Arc<AppState>,
+        config: &AnalyticsConfig,
+        mut receiver: mpsc::Receiver<PageView>,
+    ) -> Self {
+        let batch_size = config.batch_size;
+        let (stop, mut stopped) = oneshot::channel();
 
-    let client = posthog_rs::client(options).await;
+        let handle = tokio::spawn(async move {
+            let mut batch = Vec::with_capacity(batch_size);
+            loop {
+                tokio::select! {
+                    // stop accepting events, but keep draining what is already queued
+                    _ = &mut stopped, if !receiver.is_closed() => receiver.close(),
+                    received = receiver.recv_many(&mut batch, batch_size) => {
+                        if received == 0 {
+                            break;
+                        }
+                        capture(&state, std::mem::take(&mut batch)).await;
+                    }
+                }
+            }
+        });
 
-    log::info!("PostHog analytics enabled");
+        AnalyticsWorker { stop, handle }
+    }
+
+    /// Captures all queued events, waiting at most [`FLUSH_TIMEOUT`].
+    pub async fn flush(self) {
+        self.stop.send(()).ok();
+        match tokio::time::timeout(FLUSH_TIMEOUT, self.handle).await {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::error!("Analytics worker failed: {err}"),
+            Err(_) => tracing::warn!("Timed out flushing analytics events, dropping them"),
+        }
+    }
+}
+
+async fn capture(state: &AppState, events: Vec<PageView>) {
+    let count = events.len() as u64;
+    state.metrics.analytics_queue_depth.sub(count as i64);
+
+    for sink in state.runtime.load().analytics.capture_batch(&events).await {
+        state
+            .metrics
+            .analytics_failures
+            .with_label_values(&[sink])
+            .inc_by(count);
+    }
+}
+
+pub(crate) async fn init(config: &AnalyticsConfig) -> anyhow::Result<Analytics> {
+    if !config.enabled {
+        return Ok(Analytics::default());
+    }
+
+    let mut sinks: Vec<Box<dyn AnalyticsSink>> = Vec::new();
+    if let Some(posthog) = &config.posthog {
+        sinks.push(Box::new(PostHogSink::new(posthog).await?));
+    }
+    if let Some(plausible) = &config.plausible {
+        sinks.push(Box::new(PlausibleSink::new(plausible)?));
+    }
+    if let Some(json_lines) = &config.json_lines {
+        sinks.push(Box::new(JsonLinesSink::new(json_lines)));
+    }
+    if let Some(sqlite) = &config.sqlite {
+        sinks.push(Box::new(SqliteSink::open(sqlite)?));
+    }
+    if config.stdout {
+        sinks.push(Box::new(StdoutSink));
+    }
+
+    for sink in &sinks {
+        tracing::info!("Analytics sink {} enabled", sink.name());
+    }
 
     Ok(Analytics {
-        client: Some(client),
+        sinks,
+        privacy: config.privacy.clone(),
+        ..Analytics::default()
     })
 }
 
+/// Records a page view for every request, along with what handlers resolved it to.
 pub(crate) async fn capture_analytics(
     State(state): State<Arc<AppState>>,
-    req: Request,
+    mut req: Request,
     next: Next,
 ) -> Result<Response, StatusCode> {
-    const IGNORED_PATHS: [&str; 1] = ["/health"];
+    const IGNORED_PATHS: [&str; 4] = ["/health", "/health/live", "/health/ready", "/metrics"];
 
     // headers
     let user_agent = req
@@ -68,13 +297,26 @@ pub(crate) async fn capture_analytics(
         .get(USER_AGENT)
         .and_then(|h| h.to_str().ok())
         .map(ToString::to_string);
+    let referrer_host = req
+        .headers()
+        .get(REFERER)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|it| Url::parse(it).ok())
+        .and_then(|it| it.host_str().map(ToString::to_string));
 
     // URL
     let path = req.uri().clone();
 
-    let full_url = state
-        .http
-        .frontend_url
+    let runtime = state.runtime.load_full();
+    let client_ip = runtime.http.trusted_proxies.client_ip(&req);
+    let host = match req.extensions().get::<Arc<HostSettings>>() {
+        Some(host) => host.clone(),
+        None => Arc::new(HostSettings::resolve(&runtime, &req)),
+    };
+    let privacy = runtime.analytics.privacy();
+    let opted_out = privacy.respect_opt_out && privacy::opted_out(req.headers());
+    let full_url = host
+        .public_url
         .join(
             path.path_and_query()
                 .map(|it| it.as_str())
@@ -82,25 +324,138 @@ pub(crate) async fn capture_analytics(
         )
         .ok();
 
+    let resolution = SharedResolution::default();
+    req.extensions_mut().insert(resolution.clone());
+
     let response = next.run(req).await;
 
-    if !IGNORED_PATHS.contains(&path.path())
-        && let Some(full_url) = full_url
+    let bot = privacy::is_bot(user_agent.as_deref());
+    if response.status().is_redirection() {
+        let resolution = resolution.lock().expect("resolution context poisoned");
+        state
+            .clicks
+            .record(&resolution, bot, referrer_host.as_deref());
+    }
+
+    let resolution = resolution
+        .lock()
+        .expect("resolution context poisoned")
+        .clone();
+    if let Some(error) = &resolution.error {
+        state
+            .metrics
+            .resolution_errors
+            .with_label_values(&[error.kind.as_str()])
+            .inc();
+    }
+
+    if runtime.analytics.is_enabled()
+        && !IGNORED_PATHS.contains(&path.path())
+        && let Some(mut full_url) = full_url
     {
-        let hostname = full_url.host_str().unwrap_or_default().to_string();
-
-        let event = Event::new_anon("$pageview")
-            .with("$current_url", full_url.to_string())
-            .with("$host", hostname)
-            .with("$pathname", path.path())
-            .with("status", response.status().as_u16())
-            .with("success", response.status().is_success_or_redirect())
-            .with("user_agent", user_agent);
-
-        if let Err(err) = state.analytics.capture(event).await {
-            log::error!("Unable to capture event: {err:?}")
+        privacy::strip_query(&mut full_url, &privacy.query_allowlist);
+        let mut event = PageView {
+            event: EventKind::PageView,
+            timestamp: Utc::now(),
+            url: full_url,
+            host: host.analytics_host.clone(),
+            path: path.path().to_string(),
+            status: response.status().as_u16(),
+            success: response.status().is_success_or_redirect(),
+            user_agent: None,
+            visitor_id: None,
+            bot,
+            referrer_host: None,
+            resolution,
+            client_ip: privacy::truncate_ip(client_ip),
+        };
+
+        let dropped = if opted_out {
+            Some("opted_out")
+        } else if bot && privacy.bots == BotHandling::Drop {
+            Some("bot")
+        } else {
+            None
+        };
+        match dropped {
+            // errors are operational data, so they are reported regardless
+            Some(reason) => {
+                state.analytics_queue.discard(reason);
+                if event.resolution.error.is_some() {
+                    event.event = EventKind::Exception;
+                    state.analytics_queue.push(event);
+                }
+            }
+            None => {
+                event.visitor_id = privacy.visitor_hash.then(|| {
+                    state.visitor_salt.visitor_id(
+                        &host.analytics_host,
+                        client_ip,
+                        user_agent.as_deref(),
+                    )
+                });
+                event.user_agent = user_agent.filter(|_| privacy.record_user_agent);
+                event.referrer_host = referrer_host;
+                state.analytics_queue.push(event);
+            }
         }
     }
 
     Ok(response)
 }
+
+#[cfg(test)]
+mod test {
+    use crate::analytics::{AnalyticsQueue, EventKind, PageView};
+    use crate::config::AnalyticsConfig;
+    use crate::metrics::Metrics;
+    use crate::resolve::ResolutionContext;
+    use chrono::Utc;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::Arc;
+    use url::Url;
+
+    pub(super) fn page_view(path: &str) -> PageView {
+        PageView {
+            event: EventKind::PageView,
+            timestamp: Utc::now(),
+            url: Url::parse("https://mods.cf")
+                .and_then(|it| it.join(path))
+                .expect("valid URL"),
+            host: "mods.cf".to_string(),
+            path: path.to_string(),
+            status: 303,
+            success: true,
+            user_agent: None,
+            visitor_id: None,
+            bot: false,
+            referrer_host: None,
+            resolution: ResolutionContext::default(),
+            client_ip: IpAddr::from(Ipv4Addr::LOCALHOST),
+        }
+    }
+
+    #[test]
+    fn should_drop_events_when_full() -> anyhow::Result<()> {
+        let metrics = Arc::new(Metrics::new()?);
+        let config = AnalyticsConfig {
+            queue_capacity: 2,
+            ..AnalyticsConfig::default()
+        };
+        let (queue, _receiver) = AnalyticsQueue::new(&config, metrics.clone());
+
+        for _ in 0..3 {
+            queue.push(page_view("/911456"));
+        }
+
+        assert_eq!(metrics.analytics_queue_depth.get(), 2);
+        assert_eq!(
+            metrics
+                .analytics_dropped
+                .with_label_values(&["queue_full"])
+                .get(),
+            1
+        );
+        Ok(())
+    }
+}
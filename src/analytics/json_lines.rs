@@ -0,0 +1,77 @@
+use crate::analytics::{AnalyticsSink, PageView};
+use crate::config::JsonLinesConfig;
+use anyhow::Context;
+use async_trait::async_trait;
+use std::path::PathBuf;
+use tokio::fs::OpenOptions;
+use tokio::io::AsyncWriteExt;
+
+/// Appends every page view as a line of JSON to a file.
+pub(crate) struct JsonLinesSink {
+    path: PathBuf,
+}
+
+impl JsonLinesSink {
+    pub fn new(config: &JsonLinesConfig) -> Self {
+        JsonLinesSink {
+            path: config.path.clone(),
+        }
+    }
+}
+
+#[async_trait]
+impl AnalyticsSink for JsonLinesSink {
+    fn name(&self) -> &'static str {
+        "json_lines"
+    }
+
+    async fn capture(&self, events: &[PageView]) -> anyhow::Result<()> {
+        let mut lines = Vec::new();
+        for event in events {
+            serde_json::to_writer(&mut lines, event)?;
+            lines.push(b'\n');
+        }
+
+        // reopened for every batch, so that the file can be rotated externally
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await
+            .with_context(|| format!("Unable to open {}", self.path.display()))?;
+        file.write_all(&lines).await?;
+        file.flush().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analytics::AnalyticsSink;
+    use crate::analytics::json_lines::JsonLinesSink;
+    use crate::analytics::test::page_view;
+    use crate::async_tests_with_env;
+    use crate::config::JsonLinesConfig;
+    use serde_json::Value;
+    use uuid::Uuid;
+
+    async_tests_with_env! {
+        async fn should_append_events() -> anyhow::Result<()> {
+            let path = std::env::temp_dir().join(format!("mods-cf-{}.jsonl", Uuid::new_v4()));
+            let sink = JsonLinesSink::new(&JsonLinesConfig { path: path.clone() });
+
+            sink.capture(&[page_view("/911456")]).await?;
+            sink.capture(&[page_view("/f/6774233")]).await?;
+
+            let content = std::fs::read_to_string(&path)?;
+            std::fs::remove_file(&path)?;
+            let paths = content
+                .lines()
+                .map(|line| Ok(serde_json::from_str::<Value>(line)?["path"].clone()))
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            assert_eq!(paths, ["/911456", "/f/6774233"]);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,109 @@
+use crate::analytics::{AnalyticsSink, EventKind, PageView};
+use crate::config::PlausibleConfig;
+use crate::resolve::{ErrorKind, RedirectKind};
+use anyhow::{Context, bail};
+use async_trait::async_trait;
+use reqwest::Client;
+use reqwest::header::USER_AGENT;
+use serde::Serialize;
+use std::time::Duration;
+use url::Url;
+
+/// Sends page views to the events API of Plausible or a compatible service.
+pub(crate) struct PlausibleSink {
+    client: Client,
+    endpoint: Url,
+    domain: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PlausibleEvent<'a> {
+    name: &'static str,
+    url: &'a str,
+    domain: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    referrer: Option<String>,
+    props: PlausibleProps,
+}
+
+/// Custom properties, which Plausible only accepts as scalars.
+#[derive(Serialize)]
+struct PlausibleProps {
+    status: u16,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    redirect_kind: Option<RedirectKind>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error_kind: Option<ErrorKind>,
+}
+
+impl PlausibleSink {
+    pub fn new(config: &PlausibleConfig) -> anyhow::Result<Self> {
+        let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+
+        Ok(PlausibleSink {
+            client,
+            endpoint: config.endpoint.clone(),
+            domain: config.domain.clone(),
+        })
+    }
+}
+
+#[async_trait]
+impl AnalyticsSink for PlausibleSink {
+    fn name(&self) -> &'static str {
+        "plausible"
+    }
+
+    /// The events API has no batch endpoint, so events are sent one by one.
+    async fn capture(&self, events: &[PageView]) -> anyhow::Result<()> {
+        let mut failures = 0;
+        let mut last_error = None;
+        // Plausible has no notion of errors apart from page views
+        for event in events.iter().filter(|it| it.event == EventKind::PageView) {
+            let body = PlausibleEvent {
+                name: "pageview",
+                url: event.url.as_str(),
+                domain: self.domain.as_deref().unwrap_or(&event.host),
+                referrer: event
+                    .referrer_host
+                    .as_ref()
+                    .map(|host| format!("https://{host}/")),
+                props: PlausibleProps {
+                    status: event.status,
+                    redirect_kind: event.resolution.kind,
+                    project_id: event.resolution.project_id,
+                    file_id: event.resolution.file_id,
+                    error_kind: event.resolution.error.as_ref().map(|it| it.kind),
+                },
+            };
+
+            // Plausible derives unique visitors from the user agent and the (truncated) client IP
+            let result = self
+                .client
+                .post(self.endpoint.clone())
+                .header(
+                    USER_AGENT,
+                    event.user_agent.as_deref().unwrap_or(crate::USER_AGENT),
+                )
+                .header("X-Forwarded-For", event.client_ip.to_string())
+                .json(&body)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .context("Unable to send event to Plausible");
+            if let Err(err) = result {
+                failures += 1;
+                last_error = Some(err);
+            }
+        }
+
+        match last_error {
+            Some(err) => bail!("{failures} of {} events failed: {err:#}", events.len()),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,125 @@
+use crate::analytics::{AnalyticsSink, EventKind, PageView};
+use crate::config::PostHogConfig;
+use crate::resolve::{CacheStatus, ErrorKind, RedirectKind, ResolutionError};
+use crate::util::CaptureEventProperties;
+use anyhow::anyhow;
+use async_trait::async_trait;
+use posthog_rs::{Client, ClientOptionsBuilder, Event};
+use serde_json::json;
+
+pub(crate) struct PostHogSink {
+    client: Client,
+}
+
+impl PostHogSink {
+    pub async fn new(config: &PostHogConfig) -> anyhow::Result<Self> {
+        let options = ClientOptionsBuilder::default()
+            .host(config.instance_url.as_str().trim_end_matches('/'))
+            .api_key(config.project_api_key.clone())
+            .personal_api_key(config.personal_api_key.clone().unwrap_or_default())
+            .build()?;
+
+        Ok(PostHogSink {
+            client: posthog_rs::client(options).await,
+        })
+    }
+}
+
+#[async_trait]
+impl AnalyticsSink for PostHogSink {
+    fn name(&self) -> &'static str {
+        "posthog"
+    }
+
+    async fn capture(&self, events: &[PageView]) -> anyhow::Result<()> {
+        let mut captured = Vec::with_capacity(events.len());
+        for event in events {
+            if event.event == EventKind::PageView {
+                captured.push(page_view(event));
+            }
+            if let Some(error) = &event.resolution.error {
+                captured.push(exception(event, error));
+            }
+        }
+
+        self.client
+            .capture_batch(captured, false)
+            .await
+            .map_err(|err| anyhow!(err))
+    }
+}
+
+fn new_event(name: &str, event: &PageView) -> Event {
+    match &event.visitor_id {
+        Some(visitor_id) => Event::new(name, visitor_id),
+        None => Event::new_anon(name),
+    }
+}
+
+fn page_view(event: &PageView) -> Event {
+    let resolution = &event.resolution;
+    new_event("$pageview", event)
+        .with("$current_url", event.url.to_string())
+        .with("$host", event.host.clone())
+        .with("$pathname", event.path.clone())
+        .with("status", event.status)
+        .with("success", event.success)
+        .with("user_agent", event.user_agent.clone())
+        .with("bot", event.bot)
+        .with("$referring_domain", event.referrer_host.clone())
+        .with("redirect_kind", resolution.kind.map(RedirectKind::as_str))
+        .with("alias", resolution.alias.clone())
+        .with("project_id", resolution.project_id)
+        .with("file_id", resolution.file_id)
+        .with("game_id", resolution.game_id)
+        .with("class_id", resolution.class_id)
+        .with("cache", resolution.cache.map(CacheStatus::as_str))
+        .with("upstream_latency_ms", resolution.upstream_latency_ms)
+        .with(
+            "error_kind",
+            resolution.error.as_ref().map(|it| it.kind.as_str()),
+        )
+}
+
+/// Builds an event in PostHog's error tracking format, with one exception per cause of the error.
+fn exception(event: &PageView, error: &ResolutionError) -> Event {
+    let resolution = &event.resolution;
+    let exceptions: Vec<_> = error
+        .chain
+        .iter()
+        .enumerate()
+        .map(|(index, message)| {
+            json!({
+                "type": if index == 0 { exception_type(error.kind) } else { "Cause" },
+                "value": message,
+                "mechanism": { "handled": true, "synthetic": false, "type": "generic" },
+            })
+        })
+        .collect();
+
+    new_event("$exception", event)
+        .with("$exception_list", exceptions)
+        .with("$exception_level", "error")
+        .with("$current_url", event.url.to_string())
+        .with("$host", event.host.clone())
+        .with("$pathname", event.path.clone())
+        .with("status", event.status)
+        .with("error_kind", error.kind.as_str())
+        .with("decode_path", error.decode_path.clone())
+        .with("redirect_kind", resolution.kind.map(RedirectKind::as_str))
+        .with("alias", resolution.alias.clone())
+        .with("project_id", resolution.project_id)
+        .with("file_id", resolution.file_id)
+        .with("game_id", resolution.game_id)
+        .with("class_id", resolution.class_id)
+        .with("cache", resolution.cache.map(CacheStatus::as_str))
+}
+
+fn exception_type(kind: ErrorKind) -> &'static str {
+    match kind {
+        ErrorKind::Decode => "DecodeError",
+        ErrorKind::Upstream => "UpstreamError",
+        ErrorKind::UnexpectedResult => "UnexpectedResultError",
+        ErrorKind::Internal => "InternalError",
+    }
+}
@@ -0,0 +1,147 @@
+use axum::http::HeaderMap;
+use chrono::{NaiveDate, Utc};
+use sha2::{Digest, Sha256};
+use std::net::IpAddr;
+use std::sync::Mutex;
+use url::Url;
+
+/// Substrings of user agents of crawlers, link preview bots and HTTP libraries, in lowercase.
+const BOT_MARKERS: [&str; 16] = [
+    "bot",
+    "crawl",
+    "spider",
+    "slurp",
+    "preview",
+    "facebookexternalhit",
+    "embedly",
+    "whatsapp",
+    "skypeuripreview",
+    "headless",
+    "lighthouse",
+    "curl/",
+    "wget/",
+    "python-requests",
+    "go-http-client",
+    "okhttp",
+];
+
+/// Secret mixed into visitor hashes, replaced at midnight UTC so that visitors can't be followed
+/// across days. It is only kept in memory.
+#[derive(Default)]
+pub(crate) struct DailySalt {
+    current: Mutex<Option<(NaiveDate, [u8; 32])>>,
+}
+
+impl DailySalt {
+    /// Derives an identifier that is stable for a visitor of a host for the rest of the day, without
+    /// the IP address being recoverable from it.
+    pub fn visitor_id(&self, host: &str, ip: IpAddr, user_agent: Option<&str>) -> String {
+        let today = Utc::now().date_naive();
+        let salt = {
+            let mut current = self.current.lock().expect("visitor salt poisoned");
+            match *current {
+                Some((date, salt)) if date == today => salt,
+                _ => {
+                    let salt = rand::random();
+                    *current = Some((today, salt));
+                    salt
+                }
+            }
+        };
+
+        let mut hasher = Sha256::new();
+        hasher.update(salt);
+        hasher.update(host.as_bytes());
+        hasher.update(ip.to_string().as_bytes());
+        hasher.update(user_agent.unwrap_or_default().as_bytes());
+        hex::encode(&hasher.finalize()[..16])
+    }
+}
+
+/// Whether the client asked not to be tracked, via `DNT: 1` or `Sec-GPC: 1`.
+pub(crate) fn opted_out(headers: &HeaderMap) -> bool {
+    ["DNT", "Sec-GPC"].into_iter().any(|name| {
+        headers
+            .get(name)
+            .is_some_and(|value| value.as_bytes() == b"1")
+    })
+}
+
+/// Whether a user agent belongs to a crawler, link preview bot or script. Requests without a user
+/// agent are assumed to be automated as well.
+pub(crate) fn is_bot(user_agent: Option<&str>) -> bool {
+    let Some(user_agent) = user_agent.filter(|it| !it.trim().is_empty()) else {
+        return true;
+    };
+    let user_agent = user_agent.to_ascii_lowercase();
+    BOT_MARKERS.iter().any(|marker| user_agent.contains(marker))
+}
+
+/// Zeroes the host part of an address, keeping a /24 of IPv4 and a /48 of IPv6 addresses.
+pub(crate) fn truncate_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, c, _] = ip.octets();
+            IpAddr::from([a, b, c, 0])
+        }
+        IpAddr::V6(ip) => {
+            let mut octets = ip.octets();
+            octets[6..].fill(0);
+            IpAddr::from(octets)
+        }
+    }
+}
+
+/// Removes all query parameters that aren't allowlisted.
+pub(crate) fn strip_query(url: &mut Url, allowlist: &[String]) {
+    let kept: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| allowlist.iter().any(|it| it == key))
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+
+    if kept.is_empty() {
+        url.set_query(None);
+    } else {
+        url.query_pairs_mut().clear().extend_pairs(kept);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analytics::privacy::{is_bot, strip_query, truncate_ip};
+    use std::net::IpAddr;
+    use url::Url;
+
+    #[test]
+    fn should_detect_bots() {
+        assert!(is_bot(None));
+        assert!(is_bot(Some(
+            "Mozilla/5.0 (compatible; Discordbot/2.0; +https://discordapp.com)"
+        )));
+        assert!(is_bot(Some("curl/8.5.0")));
+        assert!(!is_bot(Some(
+            "Mozilla/5.0 (X11; Linux x86_64; rv:131.0) Gecko/20100101 Firefox/131.0"
+        )));
+    }
+
+    #[test]
+    fn should_truncate_ips() {
+        let truncate = |ip: &str| truncate_ip(ip.parse::<IpAddr>().unwrap()).to_string();
+        assert_eq!(truncate("203.0.113.42"), "203.0.113.0");
+        assert_eq!(truncate("2001:db8:1234:5678::1"), "2001:db8:1234::");
+    }
+
+    #[test]
+    fn should_strip_query() {
+        let allowlist = ["utm_source".to_string()];
+
+        let mut url = Url::parse("https://mods.cf/911456?utm_source=discord&token=secret").unwrap();
+        strip_query(&mut url, &allowlist);
+        assert_eq!(url.as_str(), "https://mods.cf/911456?utm_source=discord");
+
+        let mut url = Url::parse("https://mods.cf/911456?token=secret").unwrap();
+        strip_query(&mut url, &allowlist);
+        assert_eq!(url.as_str(), "https://mods.cf/911456");
+    }
+}
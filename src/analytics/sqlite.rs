@@ -0,0 +1,211 @@
+use crate::analytics::{AnalyticsSink, PageView};
+use crate::config::SqliteConfig;
+use crate::resolve::{CacheStatus, RedirectKind};
+use anyhow::Context;
+use async_trait::async_trait;
+use rusqlite::{Connection, params};
+use std::sync::{Arc, Mutex};
+
+/// Columns added to `pageviews` after it was first created, in order.
+///
+/// The schema version stored in `PRAGMA user_version` is the number of applied migrations.
+const MIGRATIONS: &[&[(&str, &str)]] = &[
+    &[
+        ("visitor_id", "TEXT"),
+        ("bot", "INTEGER NOT NULL DEFAULT 0"),
+    ],
+    &[
+        ("referrer_host", "TEXT"),
+        ("redirect_kind", "TEXT"),
+        ("alias", "TEXT"),
+        ("project_id", "INTEGER"),
+        ("file_id", "INTEGER"),
+        ("game_id", "INTEGER"),
+        ("class_id", "INTEGER"),
+        ("cache", "TEXT"),
+        ("upstream_latency_ms", "INTEGER"),
+    ],
+    &[("error_kind", "TEXT"), ("error", "TEXT")],
+    &[("event", "TEXT NOT NULL DEFAULT 'page_view'")],
+];
+
+/// Creates the `pageviews` table or brings an existing one up to date.
+///
+/// Databases written before schema versions were tracked may already have some of the columns of
+/// a migration, so only missing columns are added.
+fn migrate(connection: &mut Connection) -> anyhow::Result<()> {
+    let txn = connection.transaction()?;
+    txn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS pageviews (
+            timestamp TEXT NOT NULL,
+            url TEXT NOT NULL,
+            host TEXT NOT NULL,
+            path TEXT NOT NULL,
+            status INTEGER NOT NULL,
+            success INTEGER NOT NULL,
+            user_agent TEXT
+        );",
+    )?;
+
+    let version: usize = txn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    if version < MIGRATIONS.len() {
+        let mut columns = txn
+            .prepare("SELECT name FROM pragma_table_info('pageviews')")?
+            .query_map([], |row| row.get::<_, String>(0))?
+            .collect::<Result<Vec<_>, _>>()?;
+        for (name, definition) in MIGRATIONS.iter().skip(version).copied().flatten() {
+            if !columns.iter().any(|it| it == name) {
+                txn.execute_batch(&format!(
+                    "ALTER TABLE pageviews ADD COLUMN {name} {definition};"
+                ))?;
+                columns.push(name.to_string());
+            }
+        }
+        txn.pragma_update(None, "user_version", MIGRATIONS.len())?;
+    }
+
+    txn.execute_batch(
+        "CREATE INDEX IF NOT EXISTS pageviews_project_id ON pageviews (project_id);
+        CREATE INDEX IF NOT EXISTS pageviews_timestamp ON pageviews (timestamp);",
+    )?;
+    txn.commit()?;
+
+    Ok(())
+}
+
+/// Stores page views in a local SQLite database, for self-hosters without an analytics service.
+pub(crate) struct SqliteSink {
+    connection: Arc<Mutex<Connection>>,
+}
+
+impl SqliteSink {
+    pub fn open(config: &SqliteConfig) -> anyhow::Result<Self> {
+        let mut connection = Connection::open(&config.path).with_context(|| {
+            format!(
+                "Unable to open analytics database {}",
+                config.path.display()
+            )
+        })?;
+        migrate(&mut connection).with_context(|| {
+            format!(
+                "Unable to migrate analytics database {}",
+                config.path.display()
+            )
+        })?;
+
+        Ok(SqliteSink {
+            connection: Arc::new(Mutex::new(connection)),
+        })
+    }
+}
+
+#[async_trait]
+impl AnalyticsSink for SqliteSink {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    async fn capture(&self, events: &[PageView]) -> anyhow::Result<()> {
+        let connection = self.connection.clone();
+        let events = events.to_vec();
+
+        tokio::task::spawn_blocking(move || {
+            let mut connection = connection.lock().expect("analytics database poisoned");
+            let txn = connection.transaction()?;
+            {
+                let mut insert = txn.prepare_cached(
+                    "INSERT INTO pageviews (
+                        timestamp, url, host, path, status, success, user_agent, visitor_id, bot,
+                        referrer_host, redirect_kind, alias, project_id, file_id, game_id,
+                        class_id, cache, upstream_latency_ms, error_kind, error, event
+                    )
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16,
+                        ?17, ?18, ?19, ?20, ?21)",
+                )?;
+                for event in &events {
+                    let resolution = &event.resolution;
+                    insert.execute(params![
+                        event.timestamp.to_rfc3339(),
+                        event.url.as_str(),
+                        event.host,
+                        event.path,
+                        event.status,
+                        event.success,
+                        event.user_agent,
+                        event.visitor_id,
+                        event.bot,
+                        event.referrer_host,
+                        resolution.kind.map(RedirectKind::as_str),
+                        resolution.alias,
+                        resolution.project_id,
+                        resolution.file_id,
+                        resolution.game_id,
+                        resolution.class_id,
+                        resolution.cache.map(CacheStatus::as_str),
+                        resolution.upstream_latency_ms,
+                        resolution.error.as_ref().map(|it| it.kind.as_str()),
+                        resolution.error.as_ref().map(|it| it.chain.join(": ")),
+                        event.event.as_str(),
+                    ])?;
+                }
+            }
+            txn.commit()?;
+
+            Ok(())
+        })
+        .await?
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::analytics::AnalyticsSink;
+    use crate::analytics::sqlite::{MIGRATIONS, SqliteSink};
+    use crate::analytics::test::page_view;
+    use crate::async_tests_with_env;
+    use crate::config::SqliteConfig;
+    use rusqlite::Connection;
+    use uuid::Uuid;
+
+    async_tests_with_env! {
+        async fn should_migrate_existing_databases() -> anyhow::Result<()> {
+            let path = std::env::temp_dir().join(format!("mods-cf-{}.sqlite", Uuid::new_v4()));
+            // as created before schema versions were tracked
+            Connection::open(&path)?.execute_batch(
+                "CREATE TABLE pageviews (
+                    timestamp TEXT NOT NULL,
+                    url TEXT NOT NULL,
+                    host TEXT NOT NULL,
+                    path TEXT NOT NULL,
+                    status INTEGER NOT NULL,
+                    success INTEGER NOT NULL,
+                    user_agent TEXT,
+                    visitor_id TEXT,
+                    bot INTEGER NOT NULL
+                );
+                INSERT INTO pageviews VALUES ('', '', '', '/1', 303, 1, NULL, NULL, 0);",
+            )?;
+
+            let sink = SqliteSink::open(&SqliteConfig { path: path.clone() })?;
+            sink.capture(&[page_view("/911456")]).await?;
+            drop(sink);
+            // reopening an up to date database is a no-op
+            let sink = SqliteSink::open(&SqliteConfig { path: path.clone() })?;
+            drop(sink);
+
+            let connection = Connection::open(&path)?;
+            let version: usize =
+                connection.pragma_query_value(None, "user_version", |row| row.get(0))?;
+            assert_eq!(version, MIGRATIONS.len());
+            let paths = connection
+                .prepare("SELECT path FROM pageviews WHERE error IS NULL ORDER BY rowid")?
+                .query_map([], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(paths, ["/1", "/911456"]);
+
+            drop(connection);
+            std::fs::remove_file(&path)?;
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use crate::analytics::{AnalyticsSink, PageView};
+use async_trait::async_trait;
+use std::io::Write;
+
+/// Prints every page view as a line of JSON, e.g. for collection by a log shipper.
+///
+/// Logs are written to stderr, so stdout only ever carries page views.
+pub(crate) struct StdoutSink;
+
+#[async_trait]
+impl AnalyticsSink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    async fn capture(&self, events: &[PageView]) -> anyhow::Result<()> {
+        let mut lines = Vec::new();
+        for event in events {
+            serde_json::to_writer(&mut lines, event)?;
+            lines.push(b'\n');
+        }
+
+        tokio::task::spawn_blocking(move || {
+            let mut stdout = std::io::stdout().lock();
+            stdout.write_all(&lines)?;
+            stdout.flush()
+        })
+        .await??;
+
+        Ok(())
+    }
+}
@@ -1,21 +1,84 @@
 use anyhow::Context;
-use mods_cf::web;
-use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use clap::Parser;
+use mods_cf::config::{Cli, Config};
+use mods_cf::{telemetry, web};
+use std::net::SocketAddr;
+use std::time::Duration;
 use tokio::net::TcpListener;
-
-const PORT: u16 = 3000;
+use tokio::sync::oneshot;
 
 #[dotenvy::load(required = false)]
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    env_logger::init();
+    let cli = Cli::parse();
+    let _telemetry = telemetry::init()?;
+    let config = Config::load(&cli)?;
 
-    let app = web::init_router(true).await?;
-    let listener = TcpListener::bind(SocketAddr::new(IpAddr::from(Ipv6Addr::UNSPECIFIED), PORT))
+    let app = web::init_app(&cli, &config).await?;
+    let address = SocketAddr::new(config.server.listen_address, config.server.port);
+    let listener = TcpListener::bind(address)
         .await
-        .with_context(|| format!("Unable to create listener on port {PORT}"))?;
+        .with_context(|| format!("Unable to create listener on {address}"))?;
+
+    tracing::info!("Listening on http://{address}");
+    let (stop_tx, stop_rx) = oneshot::channel::<()>();
+    let server = axum::serve(
+        listener,
+        app.router
+            .clone()
+            .into_make_service_with_connect_info::<SocketAddr>(),
+    )
+    .with_graceful_shutdown(async {
+        stop_rx.await.ok();
+    })
+    .into_future();
+    tokio::pin!(server);
 
-    log::info!("Listening on http://localhost:{PORT}");
-    axum::serve(listener, app).await?;
+    tokio::select! {
+        result = &mut server => result?,
+        _ = shutdown_signal() => {
+            let timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
+            tracing::info!("Shutting down, waiting up to {timeout:?} for in-flight requests");
+            stop_tx.send(()).ok();
+            match tokio::time::timeout(timeout, &mut server).await {
+                Ok(result) => result?,
+                Err(_) => tracing::warn!("Timed out waiting for in-flight requests, dropping them"),
+            }
+        }
+    }
+
+    app.shutdown().await;
+    tracing::info!("Shutdown complete");
     Ok(())
 }
+
+/// Completes once the process is asked to terminate, via Ctrl+C/SIGINT or SIGTERM.
+async fn shutdown_signal() {
+    let ctrl_c = async {
+        if let Err(err) = tokio::signal::ctrl_c().await {
+            tracing::error!("Unable to listen for Ctrl+C: {err}");
+            std::future::pending::<()>().await;
+        }
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut signal) => {
+                signal.recv().await;
+            }
+            Err(err) => {
+                tracing::error!("Unable to listen for SIGTERM: {err}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => {},
+        _ = terminate => {},
+    }
+}
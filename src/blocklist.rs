@@ -0,0 +1,178 @@
+use crate::config::BlockResponse;
+use crate::curseforge::mods::Mod;
+use crate::util::escape_html;
+use crate::web::AppState;
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Response};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::{Display, Formatter};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum BlockKind {
+    Project,
+    File,
+    Author,
+}
+
+impl Display for BlockKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            BlockKind::Project => "project",
+            BlockKind::File => "file",
+            BlockKind::Author => "author",
+        })
+    }
+}
+
+/// A blocklist entry managed through the admin API, in addition to those in the config.
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Block {
+    pub kind: BlockKind,
+    pub id: u64,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// A lookup that must not be served, answered as configured in `blocklist.response`.
+pub(crate) struct Blocked {
+    pub reason: Option<String>,
+    pub response: BlockResponse,
+}
+
+impl IntoResponse for Blocked {
+    fn into_response(self) -> Response {
+        match self.response {
+            BlockResponse::NotFound => StatusCode::NOT_FOUND.into_response(),
+            BlockResponse::Unavailable => {
+                let reason = self
+                    .reason
+                    .as_deref()
+                    .map(escape_html)
+                    .unwrap_or_else(|| "This content has been blocked.".to_string());
+                let body = format!(
+                    "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+                    <title>Unavailable For Legal Reasons</title>\n</head>\n<body>\n\
+                    <h1>451 Unavailable For Legal Reasons</h1>\n<p>{reason}</p>\n</body>\n</html>\n"
+                );
+                (
+                    StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS,
+                    [(CONTENT_TYPE, "text/html; charset=utf-8")],
+                    body,
+                )
+                    .into_response()
+            }
+        }
+    }
+}
+
+/// Checks a single target against the configured and the stored blocklist.
+pub(crate) fn check(state: &AppState, kind: BlockKind, id: u64) -> anyhow::Result<Option<Blocked>> {
+    let runtime = state.runtime.load();
+    let config = &runtime.blocklist;
+    let entries = match kind {
+        BlockKind::Project => &config.projects,
+        BlockKind::File => &config.files,
+        BlockKind::Author => &config.authors,
+    };
+
+    let reason = match entries.iter().find(|entry| entry.id == id) {
+        Some(entry) => Some(entry.reason.clone()),
+        None => state.store.block(kind, id)?.map(|block| block.reason),
+    };
+
+    Ok(reason.map(|reason| Blocked {
+        reason,
+        response: config.response,
+    }))
+}
+
+/// Checks a project and all of its authors.
+pub(crate) fn check_project(state: &AppState, project: &Mod) -> anyhow::Result<Option<Blocked>> {
+    if let Some(blocked) = check(state, BlockKind::Project, project.id)? {
+        return Ok(Some(blocked));
+    }
+    for author in &project.authors {
+        if let Some(blocked) = check(state, BlockKind::Author, author.id)? {
+            return Ok(Some(blocked));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Whether any author is blocked, in which case projects have to be looked up before redirecting.
+pub(crate) fn blocks_authors(state: &AppState) -> anyhow::Result<bool> {
+    if !state.runtime.load().blocklist.authors.is_empty() {
+        return Ok(true);
+    }
+
+    state.store.has_blocks(BlockKind::Author)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::blocklist::BlockKind;
+    use crate::config::{BlockEntry, BlockResponse, StoreConfig};
+    use crate::store::Store;
+    use crate::web::test::new_test_server_with;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn should_find_blocks_by_kind() -> anyhow::Result<()> {
+        let store = Store::open(&StoreConfig::default())?;
+        store.put_block(BlockKind::Project, 911456, None)?;
+        store.put_block(BlockKind::File, 6774233, None)?;
+        assert!(!store.has_blocks(BlockKind::Author)?);
+
+        store.put_block(BlockKind::Author, 1, None)?;
+        assert!(store.has_blocks(BlockKind::Author)?);
+
+        store.delete_block(BlockKind::Author, 1)?;
+        assert!(!store.has_blocks(BlockKind::Author)?);
+        assert!(store.has_blocks(BlockKind::File)?);
+        Ok(())
+    }
+
+    async_tests_with_env! {
+        async fn should_block_configured_targets() -> anyhow::Result<()> {
+            let server = new_test_server_with(|config| {
+                config.blocklist.projects.push(BlockEntry {
+                    id: 911456,
+                    reason: Some("DMCA <takedown>".to_string()),
+                });
+                config.blocklist.files.push(BlockEntry {
+                    id: 6774233,
+                    reason: None,
+                });
+            })
+            .await?;
+
+            let response = server.get("/911456").await;
+            response.assert_status(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+            response.assert_text_contains("DMCA &lt;takedown&gt;");
+
+            let response = server.get("/f/6774233").await;
+            response.assert_status(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+            Ok(())
+        }
+
+        async fn should_hide_blocked_targets() -> anyhow::Result<()> {
+            let server = new_test_server_with(|config| {
+                config.blocklist.response = BlockResponse::NotFound;
+                config.blocklist.projects.push(BlockEntry {
+                    id: 911456,
+                    reason: None,
+                });
+            })
+            .await?;
+
+            let response = server.get("/911456").await;
+            response.assert_status(StatusCode::NOT_FOUND);
+            Ok(())
+        }
+    }
+}
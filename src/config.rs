@@ -0,0 +1,775 @@
+use crate::forwarded::{AllowedHosts, HostPattern, TrustedProxies};
+use crate::rate_limit::Quota;
+use anyhow::{Context, bail};
+use clap::Parser;
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fmt::Display;
+use std::net::{IpAddr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use url::Url;
+
+const CONFIG_FILE_VAR: &str = "MODS_CF_CONFIG";
+
+/// Command line flags of the server binary.
+///
+/// Flags take precedence over environment variables, which take precedence over the config file.
+#[derive(Parser, Default, Clone, Debug)]
+#[command(version, about = "Shortlink service for mods on Curseforge")]
+pub struct Cli {
+    /// Path to a TOML config file. Can also be set via MODS_CF_CONFIG.
+    #[arg(short, long)]
+    pub config: Option<PathBuf>,
+    /// Address to listen on.
+    #[arg(long)]
+    pub listen_address: Option<IpAddr>,
+    /// Port to listen on.
+    #[arg(short, long)]
+    pub port: Option<u16>,
+    /// The full URL at which the site is served.
+    #[arg(long)]
+    pub frontend_url: Option<Url>,
+    /// Base URL of the Curseforge API.
+    #[arg(long)]
+    pub api_base_url: Option<Url>,
+    /// How long Curseforge API responses are cached, in seconds. Caching is off by default.
+    #[arg(long)]
+    pub cache_ttl: Option<u64>,
+    /// Timeout for requests to the Curseforge API, in seconds.
+    #[arg(long)]
+    pub request_timeout: Option<u64>,
+    /// Disable analytics, regardless of any other configuration.
+    #[arg(long)]
+    pub no_analytics: bool,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub server: ServerConfig,
+    pub http: HttpConfig,
+    pub curseforge: CurseforgeConfig,
+    pub cache: CacheConfig,
+    pub rate_limit: RateLimitConfig,
+    pub analytics: AnalyticsConfig,
+    pub store: StoreConfig,
+    pub admin: AdminConfig,
+    pub blocklist: BlocklistConfig,
+    pub stats: StatsConfig,
+    /// Per-domain overrides, for serving several domains from one instance.
+    pub hosts: Vec<HostConfig>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub listen_address: IpAddr,
+    pub port: u16,
+    /// How long to wait for in-flight requests to finish when shutting down, in seconds.
+    pub shutdown_timeout_secs: u64,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        ServerConfig {
+            listen_address: IpAddr::from(Ipv6Addr::UNSPECIFIED),
+            port: 3000,
+            shutdown_timeout_secs: 30,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct HttpConfig {
+    /// The full URL at which the site is served.
+    /// This is used as a fallback if the host header could not be determined, is 'localhost' or
+    /// not allowed.
+    pub frontend_url: Url,
+    /// Reverse proxies whose forwarding headers are trusted.
+    pub trusted_proxies: TrustedProxies,
+    /// Additional domains the site is served at, besides the frontend URL's.
+    pub allowed_hosts: AllowedHosts,
+    /// Where `/` redirects to, unless overridden for the requested host.
+    pub root_redirect: Url,
+}
+
+impl Default for HttpConfig {
+    fn default() -> Self {
+        HttpConfig {
+            frontend_url: Url::parse("http://localhost").expect("unable to parse localhost URL"),
+            trusted_proxies: TrustedProxies::default(),
+            allowed_hosts: AllowedHosts::default(),
+            root_redirect: Url::parse("https://www.curseforge.com")
+                .expect("unable to parse Curseforge URL"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CurseforgeConfig {
+    pub api_base_url: Url,
+    pub api_keys: Vec<String>,
+    /// File to read additional API keys from, one per line, e.g. a Docker secret.
+    pub api_key_file: Option<PathBuf>,
+    /// How often to check the key file for changes, in seconds.
+    pub key_reload_interval_secs: u64,
+    pub request_timeout_secs: u64,
+    pub connect_timeout_secs: u64,
+}
+
+impl Default for CurseforgeConfig {
+    fn default() -> Self {
+        CurseforgeConfig {
+            api_base_url: Url::parse("https://api.curseforge.com")
+                .expect("unable to parse Curseforge API URL"),
+            api_keys: Vec::new(),
+            api_key_file: None,
+            key_reload_interval_secs: 60,
+            request_timeout_secs: 10,
+            connect_timeout_secs: 5,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// How long Curseforge API responses are cached, in seconds. 0, the default, disables caching.
+    pub ttl_secs: u64,
+    pub backend: CacheBackend,
+    /// Snapshot file used by the disk backend.
+    pub path: PathBuf,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            ttl_secs: 0,
+            backend: CacheBackend::Memory,
+            path: PathBuf::from("cache.json"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheBackend {
+    /// Entries are lost on restart.
+    Memory,
+    /// Entries are restored from a snapshot file on startup and written back on shutdown.
+    Disk,
+}
+
+impl FromStr for CacheBackend {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "memory" => Ok(CacheBackend::Memory),
+            "disk" => Ok(CacheBackend::Disk),
+            _ => bail!("expected 'memory' or 'disk'"),
+        }
+    }
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StoreConfig {
+    /// Database file for aliases and blocks. Kept in memory only if unset.
+    pub path: Option<PathBuf>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsConfig {
+    /// Count clicks per project, file and alias in the store.
+    pub enabled: bool,
+    /// How often counted clicks are written to the store, in seconds.
+    pub flush_interval_secs: u64,
+    /// How many days of clicks are kept, older ones are deleted once a day.
+    pub retention_days: u32,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        StatsConfig {
+            enabled: true,
+            flush_interval_secs: 60,
+            retention_days: 365,
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct RateLimitConfig {
+    pub enabled: bool,
+    /// Routes that redirect without contacting Curseforge.
+    pub redirect: Quota,
+    /// Routes that query the Curseforge API.
+    pub lookup: Quota,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        RateLimitConfig {
+            enabled: true,
+            redirect: Quota {
+                per_minute: 120,
+                burst: 60,
+            },
+            lookup: Quota {
+                per_minute: 30,
+                burst: 10,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AnalyticsConfig {
+    pub enabled: bool,
+    /// Maximum number of events waiting to be captured, further events are dropped.
+    /// Changes require a restart.
+    pub queue_capacity: usize,
+    /// Maximum number of events captured in one request. Changes require a restart.
+    pub batch_size: usize,
+    pub posthog: Option<PostHogConfig>,
+    pub plausible: Option<PlausibleConfig>,
+    pub json_lines: Option<JsonLinesConfig>,
+    pub sqlite: Option<SqliteConfig>,
+    /// Print every page view to stdout as a line of JSON.
+    pub stdout: bool,
+    pub privacy: PrivacyConfig,
+}
+
+impl Default for AnalyticsConfig {
+    fn default() -> Self {
+        AnalyticsConfig {
+            enabled: true,
+            queue_capacity: 10_000,
+            batch_size: 100,
+            posthog: None,
+            plausible: None,
+            json_lines: None,
+            sqlite: None,
+            stdout: false,
+            privacy: PrivacyConfig::default(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PostHogConfig {
+    pub instance_url: Url,
+    pub project_api_key: String,
+    pub personal_api_key: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrivacyConfig {
+    /// Don't record page views of clients sending `DNT: 1` or `Sec-GPC: 1`.
+    pub respect_opt_out: bool,
+    /// Attach a visitor ID, derived from the client IP and user agent with a salt that rotates daily.
+    pub visitor_hash: bool,
+    pub record_user_agent: bool,
+    pub bots: BotHandling,
+    /// Query parameters kept in recorded URLs, all others are stripped.
+    pub query_allowlist: Vec<String>,
+}
+
+impl Default for PrivacyConfig {
+    fn default() -> Self {
+        PrivacyConfig {
+            respect_opt_out: true,
+            visitor_hash: false,
+            record_user_agent: true,
+            bots: BotHandling::Flag,
+            query_allowlist: Vec::new(),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BotHandling {
+    /// Record page views of bots, marked as such.
+    Flag,
+    /// Don't record page views of bots.
+    Drop,
+}
+
+impl FromStr for BotHandling {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "flag" => Ok(BotHandling::Flag),
+            "drop" => Ok(BotHandling::Drop),
+            _ => bail!("expected 'flag' or 'drop'"),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct PlausibleConfig {
+    /// The events API endpoint, e.g. `https://plausible.io/api/event`.
+    pub endpoint: Url,
+    /// The site domain as registered with Plausible. Defaults to the analytics host of each request.
+    pub domain: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct JsonLinesConfig {
+    /// File that page views are appended to.
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct SqliteConfig {
+    /// Database file that page views are stored in.
+    pub path: PathBuf,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct HostConfig {
+    /// The domain these settings apply to, `*.`-prefixed wildcards are supported.
+    /// Listed domains are implicitly allowed hosts.
+    pub domain: HostPattern,
+    /// Where `/` redirects to on this domain.
+    pub root_redirect: Option<Url>,
+    /// Only resolve projects and files of this game, e.g. 432 for Minecraft.
+    pub game_id: Option<u64>,
+    /// Host name reported to analytics, e.g. to group several domains together.
+    pub analytics_host: Option<String>,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    /// Bearer tokens accepted by the admin API. The admin API is disabled if there are none.
+    pub tokens: Vec<AdminToken>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct AdminToken {
+    /// Identifies the token holder in the audit log.
+    pub name: String,
+    /// Hex-encoded SHA-256 hash of the token, so that the token itself is never stored.
+    pub sha256: String,
+    pub scopes: Vec<AdminScope>,
+}
+
+#[derive(Deserialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum AdminScope {
+    /// Read-only access to all admin endpoints.
+    Read,
+    /// Managing aliases.
+    Alias,
+    /// Managing the blocklist.
+    Block,
+    /// Purging cached Curseforge responses.
+    Cache,
+    /// Everything, including reloading the configuration.
+    Full,
+}
+
+#[derive(Deserialize, Default, Clone)]
+#[serde(default, deny_unknown_fields)]
+pub struct BlocklistConfig {
+    /// How lookups of blocked targets are answered.
+    pub response: BlockResponse,
+    pub projects: Vec<BlockEntry>,
+    pub files: Vec<BlockEntry>,
+    /// Blocks every project, and every file of a project, with one of these authors.
+    pub authors: Vec<BlockEntry>,
+}
+
+#[derive(Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BlockEntry {
+    pub id: u64,
+    /// Shown on the 451 page, e.g. the reference of a takedown notice.
+    pub reason: Option<String>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockResponse {
+    /// 451 Unavailable For Legal Reasons, with a page stating the reason.
+    #[default]
+    Unavailable,
+    /// A plain 404, as if the target didn't exist.
+    NotFound,
+}
+
+impl FromStr for BlockResponse {
+    type Err = anyhow::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "unavailable" => Ok(BlockResponse::Unavailable),
+            "not_found" => Ok(BlockResponse::NotFound),
+            _ => bail!("expected 'unavailable' or 'not_found'"),
+        }
+    }
+}
+
+impl AdminScope {
+    /// Whether this scope allows calls that require the given scope. Every scope allows reading.
+    pub fn grants(self, required: AdminScope) -> bool {
+        self == AdminScope::Full || self == required || required == AdminScope::Read
+    }
+}
+
+impl Config {
+    /// Loads the configuration from the config file, environment variables and command line flags,
+    /// in increasing order of precedence.
+    pub fn load(cli: &Cli) -> anyhow::Result<Self> {
+        let file = cli
+            .config
+            .clone()
+            .or_else(|| env::var_os(CONFIG_FILE_VAR).map(PathBuf::from));
+
+        let mut config = match file {
+            Some(file) => Self::from_file(&file)?,
+            None => Config::default(),
+        };
+        config.apply_env()?;
+        config.apply_cli(cli);
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn from_file(file: &Path) -> anyhow::Result<Self> {
+        let content = std::fs::read_to_string(file)
+            .with_context(|| format!("Unable to read config file {}", file.display()))?;
+        toml::from_str(&content).with_context(|| format!("Invalid config file {}", file.display()))
+    }
+
+    fn apply_env(&mut self) -> anyhow::Result<()> {
+        env_override("LISTEN_ADDRESS", &mut self.server.listen_address)?;
+        env_override("PORT", &mut self.server.port)?;
+        env_override("SHUTDOWN_TIMEOUT", &mut self.server.shutdown_timeout_secs)?;
+
+        env_override("FRONTEND_URL", &mut self.http.frontend_url)?;
+        env_override("ROOT_REDIRECT", &mut self.http.root_redirect)?;
+        if let Ok(value) = env::var("TRUSTED_PROXIES") {
+            self.http.trusted_proxies = TrustedProxies::parse(&value)?;
+        }
+        if let Ok(value) = env::var("ALLOWED_HOSTS") {
+            self.http.allowed_hosts = AllowedHosts::parse(&value)?;
+        }
+
+        let curseforge = &mut self.curseforge;
+        env_override("CURSEFORGE_API_BASE_URL", &mut curseforge.api_base_url)?;
+        if let Ok(token) = env::var("CURSEFORGE_ETERNAL_API_TOKEN") {
+            curseforge.api_keys.push(token);
+        }
+        if let Ok(tokens) = env::var("CURSEFORGE_ETERNAL_API_TOKENS") {
+            curseforge
+                .api_keys
+                .extend(tokens.split(',').map(ToString::to_string));
+        }
+        if let Some(file) = env::var_os("CURSEFORGE_ETERNAL_API_TOKEN_FILE") {
+            curseforge.api_key_file = Some(PathBuf::from(file));
+        }
+        env_override(
+            "CURSEFORGE_KEY_RELOAD_INTERVAL",
+            &mut curseforge.key_reload_interval_secs,
+        )?;
+        env_override(
+            "CURSEFORGE_REQUEST_TIMEOUT",
+            &mut curseforge.request_timeout_secs,
+        )?;
+        env_override(
+            "CURSEFORGE_CONNECT_TIMEOUT",
+            &mut curseforge.connect_timeout_secs,
+        )?;
+
+        env_override("CACHE_TTL", &mut self.cache.ttl_secs)?;
+        env_override("CACHE_BACKEND", &mut self.cache.backend)?;
+        if let Some(path) = env::var_os("CACHE_PATH") {
+            self.cache.path = PathBuf::from(path);
+        }
+
+        if let Some(path) = env::var_os("STORE_PATH") {
+            self.store.path = Some(PathBuf::from(path));
+        }
+
+        env_override("STATS_ENABLED", &mut self.stats.enabled)?;
+        env_override("STATS_FLUSH_INTERVAL", &mut self.stats.flush_interval_secs)?;
+        env_override("STATS_RETENTION_DAYS", &mut self.stats.retention_days)?;
+
+        let rate_limit = &mut self.rate_limit;
+        env_override("RATE_LIMIT_ENABLED", &mut rate_limit.enabled)?;
+        env_override(
+            "RATE_LIMIT_REDIRECT_PER_MINUTE",
+            &mut rate_limit.redirect.per_minute,
+        )?;
+        env_override("RATE_LIMIT_REDIRECT_BURST", &mut rate_limit.redirect.burst)?;
+        env_override(
+            "RATE_LIMIT_LOOKUP_PER_MINUTE",
+            &mut rate_limit.lookup.per_minute,
+        )?;
+        env_override("RATE_LIMIT_LOOKUP_BURST", &mut rate_limit.lookup.burst)?;
+
+        env_override("ANALYTICS_ENABLED", &mut self.analytics.enabled)?;
+        env_override(
+            "ANALYTICS_QUEUE_CAPACITY",
+            &mut self.analytics.queue_capacity,
+        )?;
+        env_override("ANALYTICS_BATCH_SIZE", &mut self.analytics.batch_size)?;
+        if let Ok(instance_url) = env::var("POSTHOG_INSTANCE_URL") {
+            let project_api_key = env::var("POSTHOG_PROJECT_API_KEY").context(
+                "PostHog analytics are enabled but no POSTHOG_PROJECT_API_KEY was provided!",
+            )?;
+            self.analytics.posthog = Some(PostHogConfig {
+                instance_url: Url::parse(&instance_url)
+                    .context("POSTHOG_INSTANCE_URL not set to a valid URL")?,
+                project_api_key,
+                personal_api_key: env::var("POSTHOG_PERSONAL_API_KEY").ok(),
+            });
+        }
+        if let Ok(endpoint) = env::var("PLAUSIBLE_ENDPOINT") {
+            self.analytics.plausible = Some(PlausibleConfig {
+                endpoint: Url::parse(&endpoint)
+                    .context("PLAUSIBLE_ENDPOINT not set to a valid URL")?,
+                domain: env::var("PLAUSIBLE_DOMAIN").ok(),
+            });
+        }
+        if let Some(path) = env::var_os("ANALYTICS_JSON_LINES_PATH") {
+            self.analytics.json_lines = Some(JsonLinesConfig {
+                path: PathBuf::from(path),
+            });
+        }
+        if let Some(path) = env::var_os("ANALYTICS_SQLITE_PATH") {
+            self.analytics.sqlite = Some(SqliteConfig {
+                path: PathBuf::from(path),
+            });
+        }
+        env_override("ANALYTICS_STDOUT", &mut self.analytics.stdout)?;
+
+        let privacy = &mut self.analytics.privacy;
+        env_override("ANALYTICS_RESPECT_OPT_OUT", &mut privacy.respect_opt_out)?;
+        env_override("ANALYTICS_VISITOR_HASH", &mut privacy.visitor_hash)?;
+        env_override(
+            "ANALYTICS_RECORD_USER_AGENT",
+            &mut privacy.record_user_agent,
+        )?;
+        env_override("ANALYTICS_BOTS", &mut privacy.bots)?;
+        if let Ok(allowlist) = env::var("ANALYTICS_QUERY_ALLOWLIST") {
+            privacy.query_allowlist = allowlist
+                .split(',')
+                .map(str::trim)
+                .filter(|it| !it.is_empty())
+                .map(ToString::to_string)
+                .collect();
+        }
+
+        env_override("BLOCKLIST_RESPONSE", &mut self.blocklist.response)?;
+
+        if let Ok(sha256) = env::var("ADMIN_TOKEN_SHA256") {
+            self.admin.tokens.push(AdminToken {
+                name: "env".to_string(),
+                sha256,
+                scopes: vec![AdminScope::Full],
+            });
+        }
+
+        Ok(())
+    }
+
+    fn apply_cli(&mut self, cli: &Cli) {
+        if let Some(listen_address) = cli.listen_address {
+            self.server.listen_address = listen_address;
+        }
+        if let Some(port) = cli.port {
+            self.server.port = port;
+        }
+        if let Some(frontend_url) = &cli.frontend_url {
+            self.http.frontend_url = frontend_url.clone();
+        }
+        if let Some(api_base_url) = &cli.api_base_url {
+            self.curseforge.api_base_url = api_base_url.clone();
+        }
+        if let Some(cache_ttl) = cli.cache_ttl {
+            self.cache.ttl_secs = cache_ttl;
+        }
+        if let Some(request_timeout) = cli.request_timeout {
+            self.curseforge.request_timeout_secs = request_timeout;
+        }
+        if cli.no_analytics {
+            self.analytics.enabled = false;
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        let mut errors = Vec::new();
+
+        for (name, url) in [
+            ("http.frontend_url", &self.http.frontend_url),
+            ("http.root_redirect", &self.http.root_redirect),
+            ("curseforge.api_base_url", &self.curseforge.api_base_url),
+        ] {
+            if !matches!(url.scheme(), "http" | "https") {
+                errors.push(format!("{name} must be an http(s) URL"));
+            }
+        }
+
+        if self
+            .curseforge
+            .api_keys
+            .iter()
+            .all(|key| key.trim().is_empty())
+            && self.curseforge.api_key_file.is_none()
+        {
+            errors.push(
+                "No Curseforge API key configured! Please set CURSEFORGE_ETERNAL_API_TOKEN, \
+                CURSEFORGE_ETERNAL_API_TOKENS, CURSEFORGE_ETERNAL_API_TOKEN_FILE \
+                or curseforge.api_keys"
+                    .to_string(),
+            );
+        }
+        if self.curseforge.request_timeout_secs == 0 || self.curseforge.connect_timeout_secs == 0 {
+            errors.push("Curseforge timeouts must be greater than 0".to_string());
+        }
+        if self.curseforge.key_reload_interval_secs == 0 {
+            errors.push("curseforge.key_reload_interval_secs must be greater than 0".to_string());
+        }
+
+        if self.stats.flush_interval_secs == 0 {
+            errors.push("stats.flush_interval_secs must be greater than 0".to_string());
+        }
+        if self.stats.retention_days == 0 {
+            errors.push("stats.retention_days must be greater than 0".to_string());
+        }
+
+        if self.analytics.queue_capacity == 0 || self.analytics.batch_size == 0 {
+            errors.push(
+                "analytics.queue_capacity and analytics.batch_size must be greater than 0"
+                    .to_string(),
+            );
+        }
+
+        if self.cache.backend == CacheBackend::Disk && self.cache.path.as_os_str().is_empty() {
+            errors.push("cache.path must be set when using the disk cache backend".to_string());
+        }
+
+        for (name, quota) in [
+            ("rate_limit.redirect", &self.rate_limit.redirect),
+            ("rate_limit.lookup", &self.rate_limit.lookup),
+        ] {
+            if quota.per_minute == 0 || quota.burst == 0 {
+                errors.push(format!(
+                    "{name}.per_minute and {name}.burst must be greater than 0"
+                ));
+            }
+        }
+
+        if let Some(posthog) = &self.analytics.posthog
+            && posthog.project_api_key.trim().is_empty()
+        {
+            errors.push("analytics.posthog.project_api_key must not be empty".to_string());
+        }
+
+        for host in &self.hosts {
+            if let Some(url) = &host.root_redirect
+                && !matches!(url.scheme(), "http" | "https")
+            {
+                errors.push(format!(
+                    "hosts.root_redirect for {} must be an http(s) URL",
+                    host.domain
+                ));
+            }
+        }
+
+        for token in &self.admin.tokens {
+            if token.sha256.len() != 64 || !token.sha256.bytes().all(|it| it.is_ascii_hexdigit()) {
+                errors.push(format!(
+                    "admin.tokens.sha256 of '{}' must be a hex-encoded SHA-256 hash",
+                    token.name
+                ));
+            }
+            if token.scopes.is_empty() {
+                errors.push(format!(
+                    "admin.tokens.scopes of '{}' must not be empty",
+                    token.name
+                ));
+            }
+        }
+
+        if !errors.is_empty() {
+            bail!("Invalid configuration:\n  - {}", errors.join("\n  - "));
+        }
+
+        Ok(())
+    }
+}
+
+/// Overrides a config value with the parsed value of an environment variable, if it is set.
+fn env_override<T>(key: &str, target: &mut T) -> anyhow::Result<()>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Ok(value) = env::var(key) {
+        *target = T::from_str(&value)
+            .map_err(|err| anyhow::anyhow!("{key} has an invalid value '{value}': {err}"))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::Config;
+
+    #[test]
+    fn should_parse_config_file() -> anyhow::Result<()> {
+        let config: Config = toml::from_str(
+            r#"
+            [server]
+            port = 8080
+
+            [http]
+            frontend_url = "https://mods.cf"
+            trusted_proxies = ["10.0.0.0/8"]
+
+            [rate_limit.lookup]
+            per_minute = 10
+            burst = 5
+            "#,
+        )?;
+
+        assert_eq!(config.server.port, 8080);
+        assert_eq!(config.http.frontend_url.as_str(), "https://mods.cf/");
+        assert_eq!(config.rate_limit.lookup.per_minute, 10);
+        assert_eq!(config.rate_limit.redirect.per_minute, 120);
+        Ok(())
+    }
+
+    #[test]
+    fn should_parse_example_config() -> anyhow::Result<()> {
+        toml::from_str::<Config>(include_str!("../config.example.toml"))?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_reject_unknown_fields() {
+        assert!(toml::from_str::<Config>("[server]\nprot = 8080").is_err());
+    }
+}
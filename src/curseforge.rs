@@ -1,31 +1,166 @@
-use reqwest::Client;
+use crate::config::Config;
+use crate::curseforge::cache::Cache;
+use crate::curseforge::keys::ApiKeyPool;
+use crate::metrics::Metrics;
+use crate::util::{CheckStatus, HealthCheck};
+use crate::web::AppState;
+use anyhow::bail;
+use chrono::{TimeDelta, Utc};
 use reqwest::header::{ACCEPT, HeaderMap, HeaderName, HeaderValue};
-use std::env;
+use reqwest::{Client, RequestBuilder, Response, StatusCode};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
 
+pub(crate) mod cache;
+pub(crate) mod fingerprints;
+pub(crate) mod keys;
 pub(crate) mod mods;
 
-const API_BASE_URL: &str = "https://api.curseforge.com";
+const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+/// How long the result of a readiness probe against the Curseforge API is reused.
+const PROBE_TTL: TimeDelta = TimeDelta::seconds(30);
 
 pub(crate) struct CurseforgeState {
     pub eternal_api_client: Client,
+    /// Base URL of the Curseforge API, without a trailing slash.
+    pub api_base_url: String,
+    pub api_keys: ApiKeyPool,
+    pub cache: Cache,
+    pub metrics: Arc<Metrics>,
+    last_probe: Mutex<Option<HealthCheck>>,
 }
 
-pub(crate) fn init() -> anyhow::Result<CurseforgeState> {
-    let eternal_api_token = env::var("CURSEFORGE_ETERNAL_API_TOKEN")
-        .expect("Please specify CURSEFORGE_ETERNAL_API_TOKEN for Curseforge Eternal API!");
+impl CurseforgeState {
+    /// Sends a request to the Curseforge API, authenticated with the next key from the pool.
+    ///
+    /// Keys that are rejected by the API are retired and the request is retried with the next one.
+    #[tracing::instrument(skip(self, request))]
+    pub async fn send(
+        &self,
+        endpoint: &str,
+        request: impl Fn(&Client) -> RequestBuilder,
+    ) -> anyhow::Result<Response> {
+        loop {
+            let Some(key) = self.api_keys.next() else {
+                self.metrics.record_upstream_error(endpoint, "no_api_key");
+                bail!("All Curseforge API keys have been rejected!");
+            };
+
+            let start = Instant::now();
+            let result = request(&self.eternal_api_client)
+                .header(API_KEY_HEADER, key.value().clone())
+                .send()
+                .await;
+            self.metrics
+                .upstream_request_duration
+                .with_label_values(&[endpoint])
+                .observe(start.elapsed().as_secs_f64());
+
+            let response = match result {
+                Ok(response) => response,
+                Err(err) => {
+                    let kind = if err.is_timeout() {
+                        "timeout"
+                    } else if err.is_connect() {
+                        "connect"
+                    } else {
+                        "request"
+                    };
+                    self.metrics.record_upstream_error(endpoint, kind);
+                    return Err(err.into());
+                }
+            };
+
+            self.metrics
+                .upstream_requests
+                .with_label_values(&[endpoint, response.status().as_str()])
+                .inc();
+            if response.status().is_server_error() {
+                self.metrics.record_upstream_error(endpoint, "server_error");
+            }
+
+            match response.status() {
+                StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => self.api_keys.retire(&key),
+                _ => return Ok(response),
+            }
+        }
+    }
+
+    /// Checks whether the Curseforge API is reachable with our keys.
+    ///
+    /// The result is cached for a short while so that frequent readiness checks don't hit the API.
+    pub async fn probe(&self) -> HealthCheck {
+        let mut last_probe = self.last_probe.lock().await;
+        if let Some(probe) = last_probe.as_ref()
+            && probe
+                .checked_at
+                .is_some_and(|checked_at| Utc::now() - checked_at < PROBE_TTL)
+        {
+            return probe.clone();
+        }
+
+        let url = format!("{}/v1/games/432", self.api_base_url);
+        let probe = match self.send("probe", |client| client.get(url.clone())).await {
+            Ok(response) if response.status().is_success() => {
+                HealthCheck::new(CheckStatus::Ok, true)
+            }
+            Ok(response) => HealthCheck::new(CheckStatus::Failing, true).with_message(format!(
+                "Curseforge API responded with {}",
+                response.status()
+            )),
+            Err(err) => {
+                HealthCheck::new(CheckStatus::Failing, true).with_message(format!("{err:#}"))
+            }
+        }
+        .checked_at(Utc::now());
+
+        *last_probe = Some(probe.clone());
+        probe
+    }
+}
+
+pub(crate) fn init(config: &Config, metrics: Arc<Metrics>) -> anyhow::Result<CurseforgeState> {
+    let curseforge = &config.curseforge;
+    let api_keys = ApiKeyPool::new(curseforge.api_keys.clone(), curseforge.api_key_file.clone())?;
 
     let mut default_headers = HeaderMap::with_capacity(4);
-    default_headers.append(
-        HeaderName::from_static("x-api-key"),
-        HeaderValue::from_str(&eternal_api_token)?,
-    );
     default_headers.append(ACCEPT, HeaderValue::from_static("application/json"));
     let client = Client::builder()
         .user_agent(crate::USER_AGENT)
         .default_headers(default_headers)
+        .timeout(Duration::from_secs(curseforge.request_timeout_secs))
+        .connect_timeout(Duration::from_secs(curseforge.connect_timeout_secs))
         .build()?;
 
     Ok(CurseforgeState {
         eternal_api_client: client,
+        api_base_url: curseforge
+            .api_base_url
+            .as_str()
+            .trim_end_matches('/')
+            .to_string(),
+        api_keys,
+        cache: Cache::new(&config.cache)?,
+        metrics,
+        last_probe: Mutex::default(),
     })
 }
+
+/// Periodically checks the API key file for changes, so that keys can be rotated without a restart.
+pub(crate) fn spawn_key_reloader(state: Arc<AppState>, config: &Config) {
+    if !state.curseforge.api_keys.watches_file() {
+        return;
+    }
+
+    let interval = Duration::from_secs(config.curseforge.key_reload_interval_secs);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        loop {
+            interval.tick().await;
+            if let Err(err) = state.curseforge.api_keys.reload_if_changed() {
+                tracing::error!("Unable to reload Curseforge API keys: {err:#}");
+            }
+        }
+    });
+}
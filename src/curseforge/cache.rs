@@ -0,0 +1,386 @@
+use crate::config::{CacheBackend, CacheConfig};
+use crate::curseforge::mods::{File, Mod};
+use crate::util::{CheckStatus, HealthCheck, from_json_value};
+use anyhow::Context;
+use chrono::{DateTime, TimeDelta, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+/// Number of entries per kind above which expired entries are evicted.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+pub(crate) struct CacheEntry<T> {
+    pub value: T,
+    pub fetched_at: DateTime<Utc>,
+    pub source: EntrySource,
+    /// The entry as returned by the API, only kept if the cache is persisted to disk.
+    raw: Option<Value>,
+}
+
+impl<T> CacheEntry<T> {
+    fn is_fresh(&self, ttl: TimeDelta) -> bool {
+        Utc::now() - self.fetched_at < ttl
+    }
+}
+
+/// Where a cache entry came from.
+#[derive(Serialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum EntrySource {
+    /// Fetched from the Curseforge API by this process.
+    Api,
+    /// Restored from the disk snapshot on startup.
+    Snapshot,
+}
+
+/// A cache entry as shown by the admin API.
+#[derive(Serialize)]
+pub(crate) struct EntryInfo<T> {
+    pub fetched_at: DateTime<Utc>,
+    pub age_secs: i64,
+    /// Seconds until the entry expires, negative if it already has.
+    pub expires_in_secs: i64,
+    pub source: EntrySource,
+    pub value: T,
+}
+
+/// Overview of the cache as shown by the admin API.
+#[derive(Serialize)]
+pub(crate) struct CacheSummary {
+    pub backend: CacheBackend,
+    pub ttl_secs: i64,
+    pub projects: usize,
+    pub files: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    fetched_at: DateTime<Utc>,
+    data: Value,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    projects: Vec<SnapshotEntry>,
+    files: Vec<SnapshotEntry>,
+}
+
+/// Cache for Curseforge API lookups.
+///
+/// Entries are always served from memory. With the disk backend, they are additionally restored
+/// from a snapshot file on startup and written back to it on shutdown.
+pub(crate) struct Cache {
+    ttl: TimeDelta,
+    snapshot_path: Option<PathBuf>,
+    projects: RwLock<HashMap<u64, CacheEntry<Mod>>>,
+    files: RwLock<HashMap<u64, CacheEntry<File>>>,
+}
+
+impl Cache {
+    pub fn new(config: &CacheConfig) -> anyhow::Result<Self> {
+        let cache = Cache {
+            ttl: TimeDelta::seconds(config.ttl_secs as i64),
+            snapshot_path: match config.backend {
+                CacheBackend::Memory => None,
+                CacheBackend::Disk => Some(config.path.clone()),
+            },
+            projects: RwLock::default(),
+            files: RwLock::default(),
+        };
+
+        if let Some(path) = &cache.snapshot_path
+            && cache.is_enabled()
+            && path.exists()
+        {
+            cache.restore(path)?;
+        }
+
+        Ok(cache)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.ttl > TimeDelta::zero()
+    }
+
+    pub fn health_check(&self) -> HealthCheck {
+        if !self.is_enabled() {
+            return HealthCheck::new(CheckStatus::Disabled, true);
+        }
+
+        let projects = self.projects.read().map(|it| it.len());
+        let files = self.files.read().map(|it| it.len());
+        let (Ok(projects), Ok(files)) = (projects, files) else {
+            return HealthCheck::new(CheckStatus::Failing, true)
+                .with_message("cache lock poisoned");
+        };
+
+        let Some(path) = &self.snapshot_path else {
+            return HealthCheck::new(CheckStatus::Ok, true).with_message(format!(
+                "in-memory, {projects} projects and {files} files cached"
+            ));
+        };
+
+        let directory = path
+            .parent()
+            .filter(|it| !it.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        match std::fs::metadata(directory) {
+            Ok(metadata) if metadata.is_dir() && !metadata.permissions().readonly() => {
+                HealthCheck::new(CheckStatus::Ok, true).with_message(format!(
+                    "disk ({}), {projects} projects and {files} files cached",
+                    path.display()
+                ))
+            }
+            // lookups still work from memory, the cache just won't survive a restart
+            _ => HealthCheck::new(CheckStatus::Degraded, true).with_message(format!(
+                "snapshot directory {} is not writable",
+                directory.display()
+            )),
+        }
+    }
+
+    pub fn summary(&self) -> CacheSummary {
+        CacheSummary {
+            backend: match self.snapshot_path {
+                None => CacheBackend::Memory,
+                Some(_) => CacheBackend::Disk,
+            },
+            ttl_secs: self.ttl.num_seconds(),
+            projects: self.projects.read().expect("project cache poisoned").len(),
+            files: self.files.read().expect("file cache poisoned").len(),
+        }
+    }
+
+    pub fn project(&self, project_id: u64) -> Option<Mod> {
+        let projects = self.projects.read().expect("project cache poisoned");
+        projects
+            .get(&project_id)
+            .filter(|entry| entry.is_fresh(self.ttl))
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Whether a fresh entry for the project exists, without cloning it.
+    pub fn contains_project(&self, project_id: u64) -> bool {
+        let projects = self.projects.read().expect("project cache poisoned");
+        projects
+            .get(&project_id)
+            .is_some_and(|entry| entry.is_fresh(self.ttl))
+    }
+
+    pub fn insert_project(&self, project: &Mod, raw: &Value) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let entry = self.entry(project.clone(), raw, Utc::now(), EntrySource::Api);
+        let mut projects = self.projects.write().expect("project cache poisoned");
+        if projects.len() >= PRUNE_THRESHOLD {
+            projects.retain(|_, entry| entry.is_fresh(self.ttl));
+        }
+        projects.insert(project.id, entry);
+    }
+
+    pub fn file(&self, file_id: u64) -> Option<File> {
+        let files = self.files.read().expect("file cache poisoned");
+        files
+            .get(&file_id)
+            .filter(|entry| entry.is_fresh(self.ttl))
+            .map(|entry| entry.value.clone())
+    }
+
+    /// Whether a fresh entry for the file exists, without cloning it.
+    pub fn contains_file(&self, file_id: u64) -> bool {
+        let files = self.files.read().expect("file cache poisoned");
+        files
+            .get(&file_id)
+            .is_some_and(|entry| entry.is_fresh(self.ttl))
+    }
+
+    /// Whether fresh entries for the file and its project exist, without cloning them.
+    pub fn contains_file_info(&self, file_id: u64) -> bool {
+        let project_id = {
+            let files = self.files.read().expect("file cache poisoned");
+            match files.get(&file_id) {
+                Some(entry) if entry.is_fresh(self.ttl) => entry.value.project_id,
+                _ => return false,
+            }
+        };
+        self.contains_project(project_id)
+    }
+
+    pub fn insert_files<'a>(&self, new_files: impl IntoIterator<Item = (&'a File, &'a Value)>) {
+        if !self.is_enabled() {
+            return;
+        }
+
+        let now = Utc::now();
+        let mut files = self.files.write().expect("file cache poisoned");
+        if files.len() >= PRUNE_THRESHOLD {
+            files.retain(|_, entry| entry.is_fresh(self.ttl));
+        }
+        for (file, raw) in new_files {
+            files.insert(
+                file.id,
+                self.entry(file.clone(), raw, now, EntrySource::Api),
+            );
+        }
+    }
+
+    /// Returns a cached project including its metadata, even if it has expired.
+    pub fn project_info(&self, project_id: u64) -> Option<EntryInfo<Mod>> {
+        let projects = self.projects.read().expect("project cache poisoned");
+        projects.get(&project_id).map(|entry| self.info(entry))
+    }
+
+    /// Returns a cached file including its metadata, even if it has expired.
+    pub fn file_info(&self, file_id: u64) -> Option<EntryInfo<File>> {
+        let files = self.files.read().expect("file cache poisoned");
+        files.get(&file_id).map(|entry| self.info(entry))
+    }
+
+    /// Removes a project from the cache, returning whether it was cached.
+    pub fn purge_project(&self, project_id: u64) -> bool {
+        let mut projects = self.projects.write().expect("project cache poisoned");
+        projects.remove(&project_id).is_some()
+    }
+
+    /// Removes a file from the cache, returning whether it was cached.
+    pub fn purge_file(&self, file_id: u64) -> bool {
+        let mut files = self.files.write().expect("file cache poisoned");
+        files.remove(&file_id).is_some()
+    }
+
+    /// Empties the cache, returning the number of purged projects and files.
+    pub fn purge_all(&self) -> (usize, usize) {
+        let mut projects = self.projects.write().expect("project cache poisoned");
+        let mut files = self.files.write().expect("file cache poisoned");
+        let purged = (projects.len(), files.len());
+        projects.clear();
+        files.clear();
+        purged
+    }
+
+    fn info<T: Clone>(&self, entry: &CacheEntry<T>) -> EntryInfo<T> {
+        let age = Utc::now() - entry.fetched_at;
+        EntryInfo {
+            fetched_at: entry.fetched_at,
+            age_secs: age.num_seconds(),
+            expires_in_secs: (self.ttl - age).num_seconds(),
+            source: entry.source,
+            value: entry.value.clone(),
+        }
+    }
+
+    fn entry<T>(
+        &self,
+        value: T,
+        raw: &Value,
+        fetched_at: DateTime<Utc>,
+        source: EntrySource,
+    ) -> CacheEntry<T> {
+        CacheEntry {
+            value,
+            fetched_at,
+            source,
+            raw: self.snapshot_path.as_ref().map(|_| raw.clone()),
+        }
+    }
+
+    fn restore(&self, path: &Path) -> anyhow::Result<()> {
+        let content = std::fs::read(path)
+            .with_context(|| format!("Unable to read cache snapshot {}", path.display()))?;
+        // a broken snapshot only costs us a cold cache, so don't refuse to start because of it
+        let snapshot: Snapshot = match serde_json::from_slice(&content) {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                tracing::warn!("Ignoring invalid cache snapshot {}: {err}", path.display());
+                return Ok(());
+            }
+        };
+
+        let projects = self.restore_entries(snapshot.projects, |project: &Mod| project.id);
+        let files = self.restore_entries(snapshot.files, |file: &File| file.id);
+        tracing::info!(
+            "Restored {} projects and {} files from {}",
+            projects.len(),
+            files.len(),
+            path.display()
+        );
+
+        *self.projects.write().expect("project cache poisoned") = projects;
+        *self.files.write().expect("file cache poisoned") = files;
+        Ok(())
+    }
+
+    fn restore_entries<T: DeserializeOwned>(
+        &self,
+        entries: Vec<SnapshotEntry>,
+        id: impl Fn(&T) -> u64,
+    ) -> HashMap<u64, CacheEntry<T>> {
+        entries
+            .into_iter()
+            .filter(|entry| Utc::now() - entry.fetched_at < self.ttl)
+            .filter_map(|entry| match from_json_value::<T>(&entry.data) {
+                Ok(value) => {
+                    let cached =
+                        self.entry(value, &entry.data, entry.fetched_at, EntrySource::Snapshot);
+                    Some((id(&cached.value), cached))
+                }
+                Err(err) => {
+                    tracing::warn!("Skipping cache snapshot entry: {err:#}");
+                    None
+                }
+            })
+            .collect()
+    }
+
+    /// Writes all fresh entries to the snapshot file, if the disk backend is used.
+    pub fn persist(&self) -> anyhow::Result<()> {
+        let Some(path) = &self.snapshot_path else {
+            return Ok(());
+        };
+
+        fn snapshot<T>(
+            entries: &HashMap<u64, CacheEntry<T>>,
+            ttl: TimeDelta,
+        ) -> Vec<SnapshotEntry> {
+            entries
+                .values()
+                .filter(|entry| entry.is_fresh(ttl))
+                .filter_map(|entry| {
+                    Some(SnapshotEntry {
+                        fetched_at: entry.fetched_at,
+                        data: entry.raw.clone()?,
+                    })
+                })
+                .collect()
+        }
+
+        let snapshot = Snapshot {
+            projects: snapshot(
+                &self.projects.read().expect("project cache poisoned"),
+                self.ttl,
+            ),
+            files: snapshot(&self.files.read().expect("file cache poisoned"), self.ttl),
+        };
+
+        // write to a temporary file first, so that a crash can't leave a truncated snapshot behind
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, serde_json::to_vec(&snapshot)?)
+            .with_context(|| format!("Unable to write cache snapshot {}", temp_path.display()))?;
+        std::fs::rename(&temp_path, path)
+            .with_context(|| format!("Unable to write cache snapshot {}", path.display()))?;
+
+        tracing::info!(
+            "Persisted {} projects and {} files to {}",
+            snapshot.projects.len(),
+            snapshot.files.len(),
+            path.display()
+        );
+        Ok(())
+    }
+}
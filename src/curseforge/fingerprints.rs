@@ -0,0 +1,168 @@
+use crate::curseforge::CurseforgeState;
+use crate::curseforge::mods::{File, UpstreamError};
+use crate::util::{BetterJsonError, from_json_value};
+use anyhow::Context;
+use reqwest::StatusCode;
+use reqwest::header::{CONTENT_TYPE, HeaderValue};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+const SEED: u32 = 1;
+const M: u32 = 0x5bd1_e995;
+const R: u32 = 24;
+
+/// Computes the fingerprint Curseforge identifies files by: MurmurHash2 with a seed of 1 over the
+/// content with all tabs, line feeds, carriage returns and spaces removed.
+pub fn fingerprint(content: &[u8]) -> u32 {
+    let normalized: Vec<u8> = content
+        .iter()
+        .copied()
+        .filter(|byte| !is_whitespace(*byte))
+        .collect();
+    murmur2(&normalized, SEED)
+}
+
+fn is_whitespace(byte: u8) -> bool {
+    matches!(byte, b'\t' | b'\n' | b'\r' | b' ')
+}
+
+fn murmur2(data: &[u8], seed: u32) -> u32 {
+    // the length is truncated to 32 bits like in Curseforge's implementation
+    let mut hash = seed ^ data.len() as u32;
+
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(M);
+        k ^= k >> R;
+        k = k.wrapping_mul(M);
+        hash = hash.wrapping_mul(M);
+        hash ^= k;
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        for (index, byte) in tail.iter().enumerate() {
+            hash ^= u32::from(*byte) << (8 * index);
+        }
+        hash = hash.wrapping_mul(M);
+    }
+
+    hash ^= hash >> 13;
+    hash = hash.wrapping_mul(M);
+    hash ^= hash >> 15;
+    hash
+}
+
+#[derive(Serialize)]
+struct GetFingerprintMatchesRequest {
+    fingerprints: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+struct GetFingerprintMatchesResponse {
+    data: FingerprintMatches,
+}
+
+#[derive(Deserialize)]
+struct FingerprintMatches {
+    #[serde(rename = "exactMatches")]
+    exact_matches: Vec<FingerprintMatch>,
+}
+
+#[derive(Deserialize)]
+struct FingerprintMatch {
+    file: Value,
+}
+
+/// Looks up the files with exactly these fingerprints.
+///
+/// Fingerprints without a match are missing from the result. Matching files are cached by their ID.
+#[tracing::instrument(skip_all, fields(fingerprints = fingerprints.len()))]
+pub async fn get_fingerprint_matches(
+    state: &CurseforgeState,
+    fingerprints: Vec<u32>,
+) -> anyhow::Result<HashMap<u32, File>> {
+    const ENDPOINT: &str = "get_fingerprint_matches";
+
+    let url = format!("{}/v1/fingerprints", state.api_base_url);
+    let req = GetFingerprintMatchesRequest { fingerprints };
+
+    let response = state
+        .send(ENDPOINT, |client| {
+            client
+                .post(url.clone())
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .json(&req)
+        })
+        .await
+        .context(url.clone())?;
+
+    if !response.status().is_success() {
+        match response.status() {
+            StatusCode::NOT_FOUND => return Ok(HashMap::new()),
+            status => return Err(UpstreamError::Status(status).into()),
+        }
+    }
+
+    let matches: GetFingerprintMatchesResponse = response
+        .json_with_error()
+        .await
+        .inspect_err(|_| state.metrics.record_upstream_error(ENDPOINT, "decode"))?;
+    let files = matches
+        .data
+        .exact_matches
+        .iter()
+        .map(|it| from_json_value::<File>(&it.file))
+        .collect::<anyhow::Result<Vec<_>>>()
+        .context("Unable to decode fingerprint matches")
+        .inspect_err(|_| state.metrics.record_upstream_error(ENDPOINT, "decode"))?;
+    state.cache.insert_files(
+        files
+            .iter()
+            .zip(matches.data.exact_matches.iter().map(|it| &it.file)),
+    );
+
+    Ok(files
+        .into_iter()
+        .filter_map(|file| Some((u32::try_from(file.fingerprint).ok()?, file)))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::curseforge::fingerprints::{fingerprint, murmur2};
+
+    /// Expected values are computed with Austin Appleby's reference `MurmurHash2` from SMHasher.
+    #[test]
+    fn should_hash_like_murmur2() {
+        assert_eq!(murmur2(b"", 0), 0);
+        assert_eq!(murmur2(b"", 1), 0x5bd1_5e36);
+        assert_eq!(murmur2(b"a", 1), 0x2550_b18c);
+        assert_eq!(murmur2(b"ab", 1), 0x64e1_50ee);
+        assert_eq!(murmur2(b"abc", 1), 0x60a4_fcc1);
+        assert_eq!(murmur2(b"abcd", 1), 0xc93f_7a16);
+        assert_eq!(
+            murmur2(b"The quick brown fox jumps over the lazy dog", 1),
+            0x1e10_49e7
+        );
+    }
+
+    #[test]
+    fn should_fingerprint_normalized_content() {
+        assert_eq!(
+            fingerprint(b"The quick brown fox jumps over the lazy dog"),
+            0xdf9f_94f7
+        );
+    }
+
+    #[test]
+    fn should_ignore_whitespace() {
+        assert_eq!(
+            fingerprint(b"public class Example {\r\n\tint value;\n}"),
+            fingerprint(b"publicclassExample{intvalue;}")
+        );
+        assert_ne!(fingerprint(b"a b"), fingerprint(b"ab\x0b"));
+    }
+}
@@ -0,0 +1,249 @@
+use anyhow::{Context, bail};
+use reqwest::header::HeaderValue;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+pub(crate) struct ApiKey {
+    value: HeaderValue,
+    /// Short, loggable identifier of the key.
+    label: String,
+    retired: AtomicBool,
+}
+
+impl ApiKey {
+    fn new(key: &str) -> anyhow::Result<Self> {
+        let mut value =
+            HeaderValue::from_str(key).context("API key contains invalid characters")?;
+        value.set_sensitive(true);
+
+        let suffix: String = key
+            .chars()
+            .skip(key.chars().count().saturating_sub(4))
+            .collect();
+        Ok(ApiKey {
+            value,
+            label: format!("…{suffix}"),
+            retired: AtomicBool::new(false),
+        })
+    }
+
+    pub fn value(&self) -> &HeaderValue {
+        &self.value
+    }
+
+    pub fn is_retired(&self) -> bool {
+        self.retired.load(Ordering::Relaxed)
+    }
+}
+
+/// Pool of Curseforge API keys that requests are spread across in round-robin fashion.
+///
+/// Keys are taken from the configuration and, optionally, a key file (e.g. a Docker secret,
+/// one key per line) that is re-read on changes.
+pub(crate) struct ApiKeyPool {
+    static_keys: Vec<String>,
+    file: Option<PathBuf>,
+    keys: RwLock<Vec<Arc<ApiKey>>>,
+    next: AtomicUsize,
+    file_modified: RwLock<Option<SystemTime>>,
+}
+
+impl ApiKeyPool {
+    pub fn new(static_keys: Vec<String>, file: Option<PathBuf>) -> anyhow::Result<Self> {
+        let pool = ApiKeyPool {
+            static_keys,
+            file,
+            keys: RwLock::default(),
+            next: AtomicUsize::new(0),
+            file_modified: RwLock::default(),
+        };
+        pool.reload()?;
+
+        Ok(pool)
+    }
+
+    pub fn watches_file(&self) -> bool {
+        self.file.is_some()
+    }
+
+    /// Re-reads all key sources, keeping the retirement state of keys that are still configured.
+    ///
+    /// Returns the number of usable keys after reloading.
+    pub fn reload(&self) -> anyhow::Result<usize> {
+        let mut values = self.static_keys.clone();
+        if let Some(file) = &self.file {
+            let content = std::fs::read_to_string(file)
+                .with_context(|| format!("Unable to read API keys from {}", file.display()))?;
+            values.extend(
+                content
+                    .lines()
+                    .flat_map(|it| it.split(','))
+                    .map(ToString::to_string),
+            );
+            *self.file_modified.write().expect("key pool poisoned") =
+                std::fs::metadata(file).and_then(|it| it.modified()).ok();
+        }
+
+        let mut values: Vec<String> = values
+            .into_iter()
+            .map(|it| it.trim().to_string())
+            .filter(|it| !it.is_empty())
+            .collect();
+        values.sort();
+        values.dedup();
+
+        if values.is_empty() {
+            bail!("No Curseforge API key configured!");
+        }
+
+        let mut keys = self.keys.write().expect("key pool poisoned");
+        let reloaded = values
+            .iter()
+            .map(|value| {
+                match keys
+                    .iter()
+                    .find(|key| key.value.as_bytes() == value.as_bytes())
+                {
+                    Some(existing) => Ok(existing.clone()),
+                    None => ApiKey::new(value).map(Arc::new),
+                }
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        *keys = reloaded;
+
+        let usable = keys.iter().filter(|key| !key.is_retired()).count();
+        tracing::info!(
+            "Loaded {} Curseforge API key(s), {usable} usable",
+            keys.len()
+        );
+        Ok(usable)
+    }
+
+    /// Reloads the key file if it was modified since the last time it was read.
+    pub fn reload_if_changed(&self) -> anyhow::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+
+        let modified = std::fs::metadata(file).and_then(|it| it.modified()).ok();
+        if modified != *self.file_modified.read().expect("key pool poisoned") {
+            self.reload()?;
+        }
+
+        Ok(())
+    }
+
+    /// Picks the next usable key, or [`None`] if every key has been retired.
+    pub fn next(&self) -> Option<Arc<ApiKey>> {
+        let keys = self.keys.read().expect("key pool poisoned");
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+
+        (0..keys.len())
+            .map(|offset| &keys[(start + offset) % keys.len()])
+            .find(|key| !key.is_retired())
+            .cloned()
+    }
+
+    /// Takes a key out of rotation, e.g. because it was revoked.
+    pub fn retire(&self, key: &ApiKey) {
+        if !key.retired.swap(true, Ordering::Relaxed) {
+            tracing::error!(
+                "Retiring Curseforge API key {} after it was rejected by the API",
+                key.label
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::curseforge::keys::ApiKeyPool;
+    use std::time::{Duration, SystemTime};
+    use uuid::Uuid;
+
+    fn next_value(pool: &ApiKeyPool) -> Option<String> {
+        pool.next()
+            .map(|key| String::from_utf8_lossy(key.value().as_bytes()).into_owned())
+    }
+
+    #[test]
+    fn should_rotate_through_usable_keys() -> anyhow::Result<()> {
+        let pool = ApiKeyPool::new(
+            vec!["b".to_string(), "a".to_string(), "c".to_string()],
+            None,
+        )?;
+
+        assert_eq!(next_value(&pool).as_deref(), Some("a"));
+        assert_eq!(next_value(&pool).as_deref(), Some("b"));
+        assert_eq!(next_value(&pool).as_deref(), Some("c"));
+        assert_eq!(next_value(&pool).as_deref(), Some("a"));
+
+        let b = pool.next().expect("key");
+        pool.retire(&b);
+        assert!(b.is_retired());
+        assert_eq!(next_value(&pool).as_deref(), Some("c"));
+        assert_eq!(next_value(&pool).as_deref(), Some("a"));
+        assert_eq!(next_value(&pool).as_deref(), Some("c"));
+
+        pool.keys
+            .read()
+            .expect("key pool poisoned")
+            .iter()
+            .for_each(|key| pool.retire(key));
+        assert!(pool.next().is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn should_keep_retired_keys_when_reloading() -> anyhow::Result<()> {
+        let path = std::env::temp_dir().join(format!("mods-cf-keys-{}", Uuid::new_v4()));
+        std::fs::write(&path, "a\nb, c\n")?;
+        let pool = ApiKeyPool::new(vec!["a".to_string()], Some(path.clone()))?;
+        assert!(pool.watches_file());
+
+        let a = pool.next().expect("key");
+        pool.retire(&a);
+        assert_eq!(pool.reload()?, 2);
+        assert!(
+            pool.keys
+                .read()
+                .expect("key pool poisoned")
+                .iter()
+                .any(|key| key.is_retired() && key.value().as_bytes() == b"a")
+        );
+
+        // unchanged files are not re-read
+        std::fs::write(&path, "a\nd\n")?;
+        let modified = *pool.file_modified.read().expect("key pool poisoned");
+        std::fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified.unwrap_or(SystemTime::UNIX_EPOCH))?;
+        pool.reload_if_changed()?;
+        assert_eq!(pool.keys.read().expect("key pool poisoned").len(), 3);
+
+        std::fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(SystemTime::now() + Duration::from_secs(60))?;
+        pool.reload_if_changed()?;
+        let values: Vec<_> = pool
+            .keys
+            .read()
+            .expect("key pool poisoned")
+            .iter()
+            .map(|key| (key.value().as_bytes().to_vec(), key.is_retired()))
+            .collect();
+        assert_eq!(values, vec![(b"a".to_vec(), true), (b"d".to_vec(), false)]);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+
+    #[test]
+    fn should_require_a_key() {
+        assert!(ApiKeyPool::new(vec![" ".to_string()], None).is_err());
+    }
+}
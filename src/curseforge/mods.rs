@@ -1,14 +1,19 @@
-use crate::curseforge::API_BASE_URL;
-use crate::util::BetterJsonError;
-use anyhow::{Context, bail};
+use crate::curseforge::CurseforgeState;
+use crate::util::{BetterJsonError, from_json_value};
+use anyhow::Context;
 use chrono::{DateTime, Utc};
+use reqwest::StatusCode;
 use reqwest::header::{CONTENT_TYPE, HeaderValue};
-use reqwest::{Client, StatusCode};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use serde_repr::Deserialize_repr;
 use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
 
-#[derive(Serialize, Deserialize)]
+/// Most IDs sent to a bulk endpoint of the Curseforge API in one request.
+const BULK_CHUNK_SIZE: usize = 50;
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Mod {
     pub id: u64,
     #[serde(rename = "gameId")]
@@ -24,7 +29,8 @@ pub struct Mod {
     pub is_featured: bool,
     #[serde(rename = "primaryCategoryId")]
     pub primary_category_id: u64,
-    // TODO categories list
+    #[serde(default)]
+    pub categories: Vec<Category>,
     #[serde(rename = "classId")]
     pub class_id: Option<u64>,
     pub authors: Vec<ModAuthor>,
@@ -59,7 +65,31 @@ pub struct Mod {
     // TODO socialLinks
 }
 
-#[derive(Serialize, Deserialize)]
+impl Mod {
+    pub fn primary_category(&self) -> Option<&Category> {
+        self.categories
+            .iter()
+            .find(|category| category.id == self.primary_category_id)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Category {
+    pub id: u64,
+    #[serde(rename = "gameId")]
+    pub game_id: Option<u64>,
+    pub name: String,
+    pub slug: Option<String>,
+    pub url: Option<String>,
+    #[serde(rename = "iconUrl")]
+    pub icon_url: Option<String>,
+    #[serde(rename = "classId")]
+    pub class_id: Option<u64>,
+    #[serde(rename = "parentCategoryId")]
+    pub parent_category_id: Option<u64>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ModLinks {
     #[serde(rename = "websiteUrl")]
     pub website_url: String,
@@ -71,7 +101,7 @@ pub struct ModLinks {
     pub sources_url: Option<String>,
 }
 
-#[derive(Serialize, Deserialize_repr)]
+#[derive(Serialize, Deserialize_repr, Clone)]
 #[repr(u8)]
 pub enum ModStatus {
     #[serde(rename = "new")]
@@ -96,7 +126,7 @@ pub enum ModStatus {
     UnderReview = 10,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ModAuthor {
     pub id: u64,
     pub name: String,
@@ -105,7 +135,7 @@ pub struct ModAuthor {
     pub avatar_url: Option<String>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ModAsset {
     pub id: u64,
     #[serde(rename = "modId")]
@@ -244,7 +274,7 @@ pub enum FileHashAlgorithm {
     MD5 = 2,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct FileIndex {
     #[serde(rename = "gameVersion")]
     pub game_version: String,
@@ -260,7 +290,7 @@ pub struct FileIndex {
     pub mod_loader: Option<ModLoaderType>,
 }
 
-#[derive(Serialize, Deserialize_repr)]
+#[derive(Serialize, Deserialize_repr, Clone)]
 #[repr(u8)]
 pub enum ModLoaderType {
     #[serde(rename = "any")]
@@ -279,9 +309,43 @@ pub enum ModLoaderType {
     NeoForge = 6,
 }
 
+/// Curseforge API responses that can't be used, told apart from other failures in error reports.
+#[derive(Debug)]
+pub enum UpstreamError {
+    /// The API responded with an unexpected status code.
+    Status(StatusCode),
+    /// The API responded successfully, but not with what was asked for.
+    UnexpectedResult(String),
+}
+
+impl Display for UpstreamError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UpstreamError::Status(status) => {
+                write!(f, "Error trying to contact Curseforge API, got {status}!")
+            }
+            UpstreamError::UnexpectedResult(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+// responses are kept as raw JSON so that they can be written to the on-disk cache as received
 #[derive(Deserialize)]
 struct GetModResponse {
-    data: Mod,
+    data: Value,
+}
+
+#[derive(Serialize)]
+struct GetModsRequest {
+    #[serde(rename = "modIds")]
+    mod_ids: Vec<u64>,
+}
+
+#[derive(Deserialize)]
+struct GetModsResponse {
+    data: Vec<Value>,
 }
 
 #[derive(Serialize)]
@@ -292,95 +356,265 @@ struct GetFilesRequest {
 
 #[derive(Deserialize)]
 struct GetFilesResponse {
-    data: Vec<File>,
+    data: Vec<Value>,
 }
 
-pub async fn get_mod(client: &Client, project_id: u64) -> anyhow::Result<Option<Mod>> {
-    let url = format!("{API_BASE_URL}/v1/mods/{project_id}");
-    let response = client.get(url.clone()).send().await.context(url.clone())?;
+#[tracing::instrument(skip(state))]
+pub async fn get_mod(state: &CurseforgeState, project_id: u64) -> anyhow::Result<Option<Mod>> {
+    const ENDPOINT: &str = "get_mod";
+
+    let cached = state.cache.project(project_id);
+    state
+        .metrics
+        .record_cache_lookup("project", cached.is_some());
+    if cached.is_some() {
+        return Ok(cached);
+    }
+
+    let url = format!("{}/v1/mods/{project_id}", state.api_base_url);
+    let response = state
+        .send(ENDPOINT, |client| client.get(url.clone()))
+        .await
+        .context(url.clone())?;
 
     if !response.status().is_success() {
         match response.status() {
             StatusCode::NOT_FOUND => return Ok(None),
-            _ => bail!("Error trying to contact Curseforge API!"),
+            status => return Err(UpstreamError::Status(status).into()),
         }
     }
 
-    let get_mod_response: GetModResponse = response.json_with_error().await?;
-    Ok(Some(get_mod_response.data))
+    let get_mod_response: GetModResponse = response
+        .json_with_error()
+        .await
+        .inspect_err(|_| state.metrics.record_upstream_error(ENDPOINT, "decode"))?;
+    let project: Mod = from_json_value(&get_mod_response.data)
+        .with_context(|| format!("Unable to decode project {project_id}"))
+        .inspect_err(|_| state.metrics.record_upstream_error(ENDPOINT, "decode"))?;
+    state.cache.insert_project(&project, &get_mod_response.data);
+    Ok(Some(project))
 }
 
-pub async fn get_files(client: &Client, file_ids: Vec<u64>) -> anyhow::Result<HashMap<u64, File>> {
-    let url = format!("{API_BASE_URL}/v1/mods/files");
+/// Number of Curseforge API calls [`get_files`] makes for these files, as only those that aren't
+/// cached are looked up.
+pub fn get_files_requests(state: &CurseforgeState, file_ids: &[u64]) -> usize {
+    file_ids
+        .iter()
+        .filter(|file_id| !state.cache.contains_file(**file_id))
+        .count()
+        .div_ceil(BULK_CHUNK_SIZE)
+}
 
-    let req = GetFilesRequest { file_ids };
+#[tracing::instrument(skip_all, fields(files = file_ids.len()))]
+pub async fn get_files(
+    state: &CurseforgeState,
+    file_ids: Vec<u64>,
+) -> anyhow::Result<HashMap<u64, File>> {
+    const ENDPOINT: &str = "get_files";
 
-    let response = client
-        .post(url.clone())
-        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
-        .json(&req)
-        .send()
-        .await
-        .context(url.clone())?;
+    let mut files = HashMap::with_capacity(file_ids.len());
+    let mut missing = Vec::new();
+    for file_id in file_ids {
+        let cached = state.cache.file(file_id);
+        state.metrics.record_cache_lookup("file", cached.is_some());
+        match cached {
+            Some(file) => {
+                files.insert(file_id, file);
+            }
+            None => missing.push(file_id),
+        }
+    }
 
-    if !response.status().is_success() {
-        match response.status() {
-            StatusCode::BAD_REQUEST => return Ok(HashMap::new()),
-            StatusCode::NOT_FOUND => return Ok(HashMap::new()),
-            _ => bail!("Error trying to contact Curseforge API!"),
+    if missing.is_empty() {
+        return Ok(files);
+    }
+
+    let url = format!("{}/v1/mods/files", state.api_base_url);
+    for chunk in missing.chunks(BULK_CHUNK_SIZE) {
+        let req = GetFilesRequest {
+            file_ids: chunk.to_vec(),
+        };
+
+        let response = state
+            .send(ENDPOINT, |client| {
+                client
+                    .post(url.clone())
+                    .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                    .json(&req)
+            })
+            .await
+            .context(url.clone())?;
+
+        if !response.status().is_success() {
+            match response.status() {
+                StatusCode::BAD_REQUEST => continue,
+                StatusCode::NOT_FOUND => continue,
+                status => return Err(UpstreamError::Status(status).into()),
+            }
         }
+
+        let get_files_response: GetFilesResponse = response
+            .json_with_error()
+            .await
+            .inspect_err(|_| state.metrics.record_upstream_error(ENDPOINT, "decode"))?;
+        let new_files = get_files_response
+            .data
+            .iter()
+            .map(from_json_value::<File>)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .context("Unable to decode files")
+            .inspect_err(|_| state.metrics.record_upstream_error(ENDPOINT, "decode"))?;
+        state
+            .cache
+            .insert_files(new_files.iter().zip(&get_files_response.data));
+        files.extend(new_files.into_iter().map(|file| (file.id, file)));
     }
 
-    let get_files_response: GetFilesResponse = response.json_with_error().await?;
-    Ok(get_files_response
-        .data
+    Ok(files)
+}
+
+/// Number of Curseforge API calls [`get_mods`] makes for these projects, as only those that aren't
+/// cached are looked up, and at most `unknown` more projects whose IDs aren't known yet.
+pub fn get_mods_requests(state: &CurseforgeState, project_ids: &[u64], unknown: usize) -> usize {
+    let uncached = project_ids
         .iter()
-        .map(|file| (file.id, file.clone()))
-        .collect())
+        .filter(|project_id| !state.cache.contains_project(**project_id))
+        .count();
+    (uncached + unknown).div_ceil(BULK_CHUNK_SIZE)
+}
+
+/// Looks up many projects at once, answering from the cache where possible.
+///
+/// Projects that don't exist are missing from the result.
+#[tracing::instrument(skip_all, fields(projects = project_ids.len()))]
+pub async fn get_mods(
+    state: &CurseforgeState,
+    project_ids: Vec<u64>,
+) -> anyhow::Result<HashMap<u64, Mod>> {
+    const ENDPOINT: &str = "get_mods";
+
+    let mut projects = HashMap::with_capacity(project_ids.len());
+    let mut missing = Vec::new();
+    for project_id in project_ids {
+        let cached = state.cache.project(project_id);
+        state
+            .metrics
+            .record_cache_lookup("project", cached.is_some());
+        match cached {
+            Some(project) => {
+                projects.insert(project_id, project);
+            }
+            None => missing.push(project_id),
+        }
+    }
+
+    let url = format!("{}/v1/mods", state.api_base_url);
+    for chunk in missing.chunks(BULK_CHUNK_SIZE) {
+        let req = GetModsRequest {
+            mod_ids: chunk.to_vec(),
+        };
+
+        let response = state
+            .send(ENDPOINT, |client| {
+                client
+                    .post(url.clone())
+                    .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                    .json(&req)
+            })
+            .await
+            .context(url.clone())?;
+
+        if !response.status().is_success() {
+            match response.status() {
+                StatusCode::BAD_REQUEST => continue,
+                StatusCode::NOT_FOUND => continue,
+                status => return Err(UpstreamError::Status(status).into()),
+            }
+        }
+
+        let get_mods_response: GetModsResponse = response
+            .json_with_error()
+            .await
+            .inspect_err(|_| state.metrics.record_upstream_error(ENDPOINT, "decode"))?;
+        for raw in &get_mods_response.data {
+            let project: Mod = from_json_value(raw)
+                .context("Unable to decode projects")
+                .inspect_err(|_| state.metrics.record_upstream_error(ENDPOINT, "decode"))?;
+            state.cache.insert_project(&project, raw);
+            projects.insert(project.id, project);
+        }
+    }
+
+    Ok(projects)
 }
 
-pub async fn get_file_info(client: &Client, file_id: u64) -> anyhow::Result<Option<(Mod, File)>> {
-    let files = get_files(client, vec![file_id]).await?;
+#[tracing::instrument(skip(state))]
+pub async fn get_file_info(
+    state: &CurseforgeState,
+    file_id: u64,
+) -> anyhow::Result<Option<(Mod, File)>> {
+    let files = get_files(state, vec![file_id]).await?;
     match files.len() {
         0 => Ok(None),
         1 => {
             let file = files.get(&file_id).unwrap();
             let project_id = file.project_id;
-            match get_mod(client, project_id).await? {
-                None => bail!("Could not find project with id {project_id} for file {file_id}"),
+            match get_mod(state, project_id).await? {
+                None => Err(UpstreamError::UnexpectedResult(format!(
+                    "Could not find project with id {project_id} for file {file_id}"
+                ))
+                .into()),
                 Some(project) => Ok(Some((project, file.clone()))),
             }
         }
-        len => bail!("Expected 1 result file, got {len}"),
+        len => Err(
+            UpstreamError::UnexpectedResult(format!("Expected 1 result file, got {len}")).into(),
+        ),
     }
 }
 
 #[cfg(test)]
 mod test {
-    use crate::curseforge::mods::get_mod;
+    use crate::config::{Cli, Config};
+    use crate::curseforge::mods::{Category, get_mod};
+    use crate::metrics::Metrics;
     use crate::{async_tests_with_env, curseforge};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[test]
+    fn should_only_require_category_id_and_name() -> anyhow::Result<()> {
+        let category: Category =
+            serde_json::from_value(json!({ "id": 421, "name": "API and Library" }))?;
+        assert_eq!(category.name, "API and Library");
+        assert!(category.url.is_none());
+        Ok(())
+    }
 
     async_tests_with_env! {
         async fn should_not_throw() -> anyhow::Result<()> {
-            let state = curseforge::init()?;
+            let config = Config::load(&Cli::default())?;
+            let state = curseforge::init(&config, Arc::new(Metrics::new()?))?;
 
-            let result = get_mod(&state.eternal_api_client, 257814).await;
+            let result = get_mod(&state, 257814).await;
             assert!(result.is_ok(), "Unable to resolve project");
             Ok(())
         }
 
         async fn project_exists() -> anyhow::Result<()> {
-            let state = curseforge::init()?;
+            let config = Config::load(&Cli::default())?;
+            let state = curseforge::init(&config, Arc::new(Metrics::new()?))?;
 
-            let result = get_mod(&state.eternal_api_client, 911456).await;
+            let result = get_mod(&state, 911456).await;
             assert!(result.is_ok_and(|p| p.is_some()), "Project not found");
             Ok(())
         }
 
         async fn validate_project_url() -> anyhow::Result<()> {
-            let state = curseforge::init()?;
+            let config = Config::load(&Cli::default())?;
+            let state = curseforge::init(&config, Arc::new(Metrics::new()?))?;
 
-            let result = get_mod(&state.eternal_api_client, 911456).await?;
+            let result = get_mod(&state, 911456).await?;
             assert!(result.is_some_and(|p| p.links.website_url.starts_with("https://www.curseforge.com/minecraft/mc-mods/")));
             Ok(())
         }
@@ -0,0 +1,391 @@
+use crate::config::HttpConfig;
+use anyhow::{Context, bail};
+use axum::extract::{ConnectInfo, Request};
+use axum::http::header::HOST;
+use axum::http::{Extensions, HeaderMap};
+use ipnet::IpNet;
+use serde::Deserialize;
+use std::fmt::{Display, Formatter};
+use std::net::{IpAddr, Ipv6Addr, SocketAddr};
+use std::str::FromStr;
+use url::Url;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+const X_FORWARDED_HOST: &str = "x-forwarded-host";
+const X_FORWARDED_PROTO: &str = "x-forwarded-proto";
+const FORWARDED: &str = "forwarded";
+
+/// Set of reverse proxies whose forwarding headers we are willing to believe.
+#[derive(Deserialize, Default, Clone)]
+#[serde(try_from = "Vec<String>")]
+pub struct TrustedProxies {
+    networks: Vec<IpNet>,
+}
+
+impl TryFrom<Vec<String>> for TrustedProxies {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Vec<String>) -> Result<Self, Self::Error> {
+        Self::parse(&value.join(","))
+    }
+}
+
+impl TrustedProxies {
+    /// Parses a comma-separated list of IP addresses and/or CIDR ranges.
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let networks = value
+            .split(',')
+            .map(str::trim)
+            .filter(|it| !it.is_empty())
+            .map(|it| {
+                IpNet::from_str(it)
+                    .or_else(|_| IpAddr::from_str(it).map(IpNet::from))
+                    .with_context(|| format!("Invalid trusted proxy address: {it}"))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(TrustedProxies { networks })
+    }
+
+    pub fn is_trusted(&self, addr: &IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        self.networks.iter().any(|net| net.contains(&addr))
+    }
+
+    /// Whether the direct peer of the request is a trusted proxy.
+    fn trusts_peer(&self, extensions: &Extensions) -> bool {
+        self.is_trusted(&peer(extensions))
+    }
+
+    /// Determines the IP address of the client that originated the request.
+    ///
+    /// Forwarding headers are only consulted if the direct peer is a trusted proxy, in which case
+    /// the chain is walked from the right and the first untrusted hop is returned.
+    pub fn client_ip(&self, req: &Request) -> IpAddr {
+        let peer = peer(req.extensions());
+        if !self.is_trusted(&peer) {
+            return peer;
+        }
+
+        let chain = forwarded_for(req.headers());
+        chain
+            .iter()
+            .rev()
+            .find(|hop| !self.is_trusted(hop))
+            .or(chain.first())
+            .copied()
+            .unwrap_or(peer)
+    }
+}
+
+/// A host name, or a `*.`-prefixed wildcard matching all of its subdomains.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(try_from = "String")]
+pub struct HostPattern(String);
+
+impl TryFrom<String> for HostPattern {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl HostPattern {
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let pattern = value.trim().to_ascii_lowercase();
+        let host = pattern.strip_prefix("*.").unwrap_or(&pattern);
+        if host.is_empty() || host.contains(['/', ':', '*', ',', ' ']) {
+            bail!("Invalid host name: {value}");
+        }
+
+        Ok(HostPattern(pattern))
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        match self.0.strip_prefix("*.") {
+            Some(domain) => host
+                .strip_suffix(domain)
+                .is_some_and(|sub| sub.len() > 1 && sub.ends_with('.')),
+            None => self.0.eq_ignore_ascii_case(host),
+        }
+    }
+}
+
+impl Display for HostPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Domains the service may be reached at, besides the frontend URL's.
+#[derive(Deserialize, Default, Clone)]
+#[serde(try_from = "Vec<String>")]
+pub struct AllowedHosts {
+    patterns: Vec<HostPattern>,
+}
+
+impl TryFrom<Vec<String>> for AllowedHosts {
+    type Error = anyhow::Error;
+
+    fn try_from(value: Vec<String>) -> Result<Self, Self::Error> {
+        Self::parse(&value.join(","))
+    }
+}
+
+impl AllowedHosts {
+    /// Parses a comma-separated list of host names, e.g. `mods.cf, *.mods.cf`.
+    pub fn parse(value: &str) -> anyhow::Result<Self> {
+        let patterns = value
+            .split(',')
+            .filter(|it| !it.trim().is_empty())
+            .map(HostPattern::parse)
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(AllowedHosts { patterns })
+    }
+
+    pub fn allow(&mut self, pattern: HostPattern) {
+        self.patterns.push(pattern);
+    }
+
+    pub fn matches(&self, host: &str) -> bool {
+        self.patterns.iter().any(|pattern| pattern.matches(host))
+    }
+}
+
+/// Determines the public base URL the client used to reach us, e.g. `https://mods.cf/`.
+///
+/// The host is taken from the `Host` header, or from `Forwarded`/`X-Forwarded-Host` if the
+/// direct peer is a trusted proxy, which may also tell us the scheme. Hosts that are not the
+/// frontend URL's or explicitly allowed, as well as `localhost`, fall back to the frontend URL.
+pub(crate) fn public_url(http: &HttpConfig, headers: &HeaderMap, extensions: &Extensions) -> Url {
+    let fallback = &http.frontend_url;
+    let proxied = http.trusted_proxies.trusts_peer(extensions);
+
+    let authority = proxied
+        .then(|| {
+            forwarded_param(headers, "host").or_else(|| first_value(headers, X_FORWARDED_HOST))
+        })
+        .flatten()
+        .or_else(|| first_value(headers, HOST.as_str()));
+    let Some(authority) = authority else {
+        return fallback.clone();
+    };
+
+    let scheme = proxied
+        .then(|| {
+            forwarded_param(headers, "proto").or_else(|| first_value(headers, X_FORWARDED_PROTO))
+        })
+        .flatten()
+        .map(|it| it.to_ascii_lowercase())
+        .filter(|it| matches!(it.as_str(), "http" | "https"))
+        .unwrap_or_else(|| "http".to_string());
+
+    let Ok(url) = Url::parse(&format!("{scheme}://{authority}/")) else {
+        return fallback.clone();
+    };
+    let Some(host) = url.host_str() else {
+        return fallback.clone();
+    };
+
+    if host.eq_ignore_ascii_case("localhost") {
+        return fallback.clone();
+    }
+    if fallback
+        .host_str()
+        .is_some_and(|it| it.eq_ignore_ascii_case(host))
+    {
+        // the frontend URL knows better whether we are served via TLS than a direct connection
+        return if proxied { url } else { fallback.clone() };
+    }
+    if !http.allowed_hosts.matches(host) {
+        tracing::debug!("Request for unknown host {host}, falling back to {fallback}");
+        return fallback.clone();
+    }
+
+    url
+}
+
+fn peer(extensions: &Extensions) -> IpAddr {
+    extensions
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip().to_canonical())
+        .unwrap_or(IpAddr::V6(Ipv6Addr::UNSPECIFIED))
+}
+
+/// Returns the first, i.e. client-facing, value of a comma-separated header.
+fn first_value(headers: &HeaderMap, name: &str) -> Option<String> {
+    headers
+        .get(name)?
+        .to_str()
+        .ok()?
+        .split(',')
+        .map(str::trim)
+        .find(|it| !it.is_empty())
+        .map(ToString::to_string)
+}
+
+/// Iterates over all `key=value` pairs of the `Forwarded` header, in order.
+fn forwarded_pairs(headers: &HeaderMap) -> impl Iterator<Item = (&str, &str)> {
+    headers
+        .get_all(FORWARDED)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .flat_map(|element| element.split(';'))
+        .filter_map(|pair| pair.trim().split_once('='))
+}
+
+/// Returns the first value of the given `Forwarded` parameter, e.g. `host` or `proto`.
+fn forwarded_param(headers: &HeaderMap, key: &str) -> Option<String> {
+    forwarded_pairs(headers)
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.trim_matches('"').to_string())
+        .filter(|it| !it.is_empty())
+}
+
+/// Collects the `for=` hops of the `Forwarded` header, falling back to `X-Forwarded-For`.
+fn forwarded_for(headers: &HeaderMap) -> Vec<IpAddr> {
+    let forwarded: Vec<IpAddr> = forwarded_pairs(headers)
+        .filter_map(|(key, value)| {
+            key.eq_ignore_ascii_case("for")
+                .then(|| parse_node(value))
+                .flatten()
+        })
+        .collect();
+
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(parse_node)
+        .collect()
+}
+
+/// Parses a single node as found in forwarding headers, e.g. `203.0.113.7`, `"[2001:db8::1]:4711"`.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(addr) = IpAddr::from_str(node) {
+        return Some(addr.to_canonical());
+    }
+    if let Ok(addr) = SocketAddr::from_str(node) {
+        return Some(addr.ip().to_canonical());
+    }
+
+    let node = node.strip_prefix('[')?;
+    let (addr, _) = node.split_once(']')?;
+    IpAddr::from_str(addr).ok()
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::HttpConfig;
+    use crate::forwarded::{AllowedHosts, TrustedProxies, public_url};
+    use axum::body::Body;
+    use axum::extract::{ConnectInfo, Request};
+    use std::net::{IpAddr, SocketAddr};
+    use url::Url;
+
+    fn request(peer: &str, header: (&str, &str)) -> Request {
+        request_with(peer, &[header])
+    }
+
+    fn request_with(peer: &str, headers: &[(&str, &str)]) -> Request {
+        let mut builder = Request::builder();
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        let mut req = builder.body(Body::empty()).unwrap();
+        req.extensions_mut()
+            .insert(ConnectInfo(SocketAddr::new(peer.parse().unwrap(), 4711)));
+        req
+    }
+
+    #[test]
+    fn should_ignore_headers_from_untrusted_peer() -> anyhow::Result<()> {
+        let proxies = TrustedProxies::parse("10.0.0.0/8")?;
+        let req = request("198.51.100.1", ("x-forwarded-for", "203.0.113.7"));
+
+        assert_eq!(proxies.client_ip(&req), "198.51.100.1".parse::<IpAddr>()?);
+        Ok(())
+    }
+
+    #[test]
+    fn should_skip_trusted_hops() -> anyhow::Result<()> {
+        let proxies = TrustedProxies::parse("10.0.0.0/8, 192.0.2.1")?;
+        let req = request(
+            "10.1.2.3",
+            ("x-forwarded-for", "198.51.100.9, 203.0.113.7, 192.0.2.1"),
+        );
+
+        assert_eq!(proxies.client_ip(&req), "203.0.113.7".parse::<IpAddr>()?);
+        Ok(())
+    }
+
+    #[test]
+    fn should_prefer_forwarded_header() -> anyhow::Result<()> {
+        let proxies = TrustedProxies::parse("10.0.0.1")?;
+        let req = request(
+            "10.0.0.1",
+            ("forwarded", "for=\"[2001:db8::1]:4711\";proto=https"),
+        );
+
+        assert_eq!(proxies.client_ip(&req), "2001:db8::1".parse::<IpAddr>()?);
+        Ok(())
+    }
+
+    fn http_config() -> anyhow::Result<HttpConfig> {
+        Ok(HttpConfig {
+            frontend_url: Url::parse("https://mods.cf")?,
+            trusted_proxies: TrustedProxies::parse("10.0.0.1")?,
+            allowed_hosts: AllowedHosts::parse("*.mods.cf, mods.example")?,
+            ..HttpConfig::default()
+        })
+    }
+
+    #[test]
+    fn should_use_host_of_request() -> anyhow::Result<()> {
+        let http = http_config()?;
+
+        let req = request("198.51.100.1", ("host", "files.mods.cf"));
+        let url = public_url(&http, req.headers(), req.extensions());
+        assert_eq!(url.as_str(), "http://files.mods.cf/");
+
+        let req = request("198.51.100.1", ("host", "evil.example"));
+        let url = public_url(&http, req.headers(), req.extensions());
+        assert_eq!(url.as_str(), "https://mods.cf/");
+        Ok(())
+    }
+
+    #[test]
+    fn should_only_trust_forwarded_host_from_proxies() -> anyhow::Result<()> {
+        let http = http_config()?;
+        let headers = [
+            ("host", "backend:3000"),
+            ("x-forwarded-host", "mods.example"),
+            ("x-forwarded-proto", "https"),
+        ];
+
+        let req = request_with("10.0.0.1", &headers);
+        let url = public_url(&http, req.headers(), req.extensions());
+        assert_eq!(url.as_str(), "https://mods.example/");
+
+        let req = request_with("198.51.100.1", &headers);
+        let url = public_url(&http, req.headers(), req.extensions());
+        assert_eq!(url.as_str(), "https://mods.cf/");
+
+        let req = request(
+            "10.0.0.1",
+            ("forwarded", "for=192.0.2.1;host=a.mods.cf;proto=https"),
+        );
+        let url = public_url(&http, req.headers(), req.extensions());
+        assert_eq!(url.as_str(), "https://a.mods.cf/");
+        Ok(())
+    }
+}
@@ -0,0 +1,110 @@
+use crate::forwarded;
+use crate::reload::RuntimeConfig;
+use crate::web::AppState;
+use axum::extract::{Request, State};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::sync::Arc;
+use url::Url;
+
+/// Settings for the domain a request came in on, available as a request extension.
+pub(crate) struct HostSettings {
+    /// Base URL the client used to reach us, for building absolute links.
+    pub public_url: Url,
+    pub root_redirect: Url,
+    /// Game that lookups are restricted to, if any.
+    pub game_id: Option<u64>,
+    /// Host name reported to analytics.
+    pub analytics_host: String,
+}
+
+impl HostSettings {
+    pub fn resolve(runtime: &RuntimeConfig, req: &Request) -> Self {
+        let public_url = forwarded::public_url(&runtime.http, req.headers(), req.extensions());
+        let host = public_url.host_str().unwrap_or_default();
+        let config = runtime
+            .hosts
+            .iter()
+            .find(|config| config.domain.matches(host));
+
+        HostSettings {
+            root_redirect: config
+                .and_then(|it| it.root_redirect.clone())
+                .unwrap_or_else(|| runtime.http.root_redirect.clone()),
+            game_id: config.and_then(|it| it.game_id),
+            analytics_host: config
+                .and_then(|it| it.analytics_host.clone())
+                .unwrap_or_else(|| host.to_string()),
+            public_url,
+        }
+    }
+
+    /// Whether lookups on this host may resolve to content of the given game.
+    pub fn allows_game(&self, game_id: u64) -> bool {
+        self.game_id.is_none_or(|it| it == game_id)
+    }
+}
+
+/// Determines the settings for the requested host and makes them available to handlers.
+pub(crate) async fn resolve_host(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let settings = HostSettings::resolve(&state.runtime.load(), &req);
+    req.extensions_mut().insert(Arc::new(settings));
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::config::HostConfig;
+    use crate::forwarded::HostPattern;
+    use crate::web::test::new_test_server_with;
+    use axum::http::header::LOCATION;
+    use reqwest::StatusCode;
+    use url::Url;
+
+    async_tests_with_env! {
+        async fn should_redirect_root_per_host() -> anyhow::Result<()> {
+            let server = new_test_server_with(|config| {
+                config.hosts.push(HostConfig {
+                    domain: HostPattern::parse("mc.example").unwrap(),
+                    root_redirect: Some(Url::parse("https://www.curseforge.com/minecraft").unwrap()),
+                    game_id: Some(432),
+                    analytics_host: None,
+                });
+            })
+            .await?;
+
+            let response = server.get("/").add_header("host", "mc.example").await;
+            response.assert_status(StatusCode::SEE_OTHER);
+            response.assert_header(LOCATION, "https://www.curseforge.com/minecraft");
+
+            let response = server.get("/").add_header("host", "other.example").await;
+            response.assert_status(StatusCode::SEE_OTHER);
+            response.assert_header(LOCATION, "https://www.curseforge.com/");
+            Ok(())
+        }
+
+        async fn should_not_resolve_projects_of_other_games() -> anyhow::Result<()> {
+            let server = new_test_server_with(|config| {
+                config.hosts.push(HostConfig {
+                    domain: HostPattern::parse("wow.example").unwrap(),
+                    root_redirect: None,
+                    game_id: Some(1),
+                    analytics_host: None,
+                });
+            })
+            .await?;
+
+            let response = server.get("/911456").add_header("host", "wow.example").await;
+            response.assert_status(StatusCode::NOT_FOUND);
+
+            let response = server.get("/911456").add_header("host", "other.example").await;
+            response.assert_status(StatusCode::SEE_OTHER);
+            Ok(())
+        }
+    }
+}
@@ -1,8 +1,20 @@
 #[macro_use]
 extern crate extension_traits;
 
+mod aliases;
 mod analytics;
+mod blocklist;
+pub mod config;
 mod curseforge;
+mod forwarded;
+mod hosts;
+mod metrics;
+mod rate_limit;
+mod reload;
+mod resolve;
+mod stats;
+mod store;
+pub mod telemetry;
 mod util;
 pub mod web;
 
@@ -0,0 +1,228 @@
+use crate::web::AppState;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use prometheus::{
+    HistogramOpts, HistogramVec, IntCounterVec, IntGauge, Opts, Registry, TextEncoder,
+};
+use std::sync::Arc;
+use std::time::Instant;
+
+const NAMESPACE: &str = "mods_cf";
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    pub http_requests: IntCounterVec,
+    pub http_request_duration: HistogramVec,
+    pub http_requests_in_flight: IntGauge,
+    pub upstream_requests: IntCounterVec,
+    pub upstream_request_duration: HistogramVec,
+    pub upstream_errors: IntCounterVec,
+    pub cache_lookups: IntCounterVec,
+    pub analytics_failures: IntCounterVec,
+    pub analytics_dropped: IntCounterVec,
+    pub analytics_queue_depth: IntGauge,
+    pub resolution_errors: IntCounterVec,
+}
+
+impl Metrics {
+    pub fn new() -> anyhow::Result<Self> {
+        let registry = Registry::new();
+
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "Number of handled HTTP requests")
+                .namespace(NAMESPACE),
+            &["route", "status"],
+        )?;
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests",
+            )
+            .namespace(NAMESPACE),
+            &["route", "status"],
+        )?;
+        let http_requests_in_flight = IntGauge::with_opts(
+            Opts::new(
+                "http_requests_in_flight",
+                "Number of HTTP requests currently being handled",
+            )
+            .namespace(NAMESPACE),
+        )?;
+        let upstream_requests = IntCounterVec::new(
+            Opts::new(
+                "upstream_requests_total",
+                "Number of requests made to the Curseforge API",
+            )
+            .namespace(NAMESPACE),
+            &["endpoint", "status"],
+        )?;
+        let upstream_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "upstream_request_duration_seconds",
+                "Latency of requests made to the Curseforge API",
+            )
+            .namespace(NAMESPACE),
+            &["endpoint"],
+        )?;
+        let upstream_errors = IntCounterVec::new(
+            Opts::new(
+                "upstream_errors_total",
+                "Number of failed requests to the Curseforge API",
+            )
+            .namespace(NAMESPACE),
+            &["endpoint", "kind"],
+        )?;
+        let cache_lookups = IntCounterVec::new(
+            Opts::new("cache_lookups_total", "Number of cache lookups").namespace(NAMESPACE),
+            &["kind", "result"],
+        )?;
+        let analytics_failures = IntCounterVec::new(
+            Opts::new(
+                "analytics_capture_failures_total",
+                "Number of analytics events that could not be captured, per sink",
+            )
+            .namespace(NAMESPACE),
+            &["sink"],
+        )?;
+        let analytics_dropped = IntCounterVec::new(
+            Opts::new(
+                "analytics_events_dropped_total",
+                "Number of analytics events dropped before being captured",
+            )
+            .namespace(NAMESPACE),
+            &["reason"],
+        )?;
+        let analytics_queue_depth = IntGauge::with_opts(
+            Opts::new(
+                "analytics_queue_depth",
+                "Number of analytics events waiting to be captured",
+            )
+            .namespace(NAMESPACE),
+        )?;
+        let resolution_errors = IntCounterVec::new(
+            Opts::new(
+                "resolution_errors_total",
+                "Number of requests that failed to resolve, whether or not they were reported to analytics",
+            )
+            .namespace(NAMESPACE),
+            &["kind"],
+        )?;
+
+        registry.register(Box::new(http_requests.clone()))?;
+        registry.register(Box::new(http_request_duration.clone()))?;
+        registry.register(Box::new(http_requests_in_flight.clone()))?;
+        registry.register(Box::new(upstream_requests.clone()))?;
+        registry.register(Box::new(upstream_request_duration.clone()))?;
+        registry.register(Box::new(upstream_errors.clone()))?;
+        registry.register(Box::new(cache_lookups.clone()))?;
+        registry.register(Box::new(analytics_failures.clone()))?;
+        registry.register(Box::new(analytics_dropped.clone()))?;
+        registry.register(Box::new(analytics_queue_depth.clone()))?;
+        registry.register(Box::new(resolution_errors.clone()))?;
+
+        Ok(Metrics {
+            registry,
+            http_requests,
+            http_request_duration,
+            http_requests_in_flight,
+            upstream_requests,
+            upstream_request_duration,
+            upstream_errors,
+            cache_lookups,
+            analytics_failures,
+            analytics_dropped,
+            analytics_queue_depth,
+            resolution_errors,
+        })
+    }
+
+    pub fn record_cache_lookup(&self, kind: &str, hit: bool) {
+        self.cache_lookups
+            .with_label_values(&[kind, if hit { "hit" } else { "miss" }])
+            .inc();
+    }
+
+    pub fn record_upstream_error(&self, endpoint: &str, kind: &str) {
+        self.upstream_errors
+            .with_label_values(&[endpoint, kind])
+            .inc();
+    }
+}
+
+/// Keeps the in-flight gauge accurate even if the request future is dropped early.
+struct InFlightGuard(IntGauge);
+
+impl InFlightGuard {
+    fn new(gauge: &IntGauge) -> Self {
+        gauge.inc();
+        InFlightGuard(gauge.clone())
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+pub(crate) async fn track_requests(
+    State(state): State<Arc<AppState>>,
+    req: Request,
+    next: Next,
+) -> Response {
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_else(|| "unmatched".to_string());
+
+    let metrics = &state.metrics;
+    let _in_flight = InFlightGuard::new(&metrics.http_requests_in_flight);
+    let start = Instant::now();
+
+    let response = next.run(req).await;
+
+    let status = response.status().as_u16().to_string();
+    metrics
+        .http_requests
+        .with_label_values(&[&route, &status])
+        .inc();
+    metrics
+        .http_request_duration
+        .with_label_values(&[&route, &status])
+        .observe(start.elapsed().as_secs_f64());
+
+    response
+}
+
+pub(crate) async fn export(State(state): State<Arc<AppState>>) -> Response {
+    match TextEncoder::new().encode_to_string(&state.metrics.registry.gather()) {
+        Ok(body) => ([(CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response(),
+        Err(err) => {
+            tracing::error!("Unable to encode metrics: {err:#}");
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::test::new_test_server;
+    use reqwest::StatusCode;
+
+    async_tests_with_env! {
+        async fn should_export_metrics() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            server.get("/911456").await;
+            let response = server.get("/metrics").await;
+            response.assert_status(StatusCode::OK);
+            response.assert_text_contains(r#"mods_cf_http_requests_total{route="/{key}",status="303"} 1"#);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use crate::config::Config;
+use crate::hosts::HostSettings;
+use crate::resolve;
+use crate::web::AppState;
+use axum::extract::{MatchedPath, Request, State};
+use axum::http::StatusCode;
+use axum::http::header::RETRY_AFTER;
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+/// Paths that are never rate limited, so that health checks and metric scrapes keep working.
+const EXEMPT_PATHS: [&str; 4] = ["/health", "/health/live", "/health/ready", "/metrics"];
+
+/// Number of tracked clients above which idle buckets are evicted.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+/// Groups routes by how expensive they are for us to serve.
+#[derive(Copy, Clone, Eq, PartialEq, Hash, Debug)]
+pub(crate) enum RouteClass {
+    /// Routes that redirect without contacting Curseforge.
+    Redirect,
+    /// Routes that trigger one or more Curseforge API calls.
+    Lookup,
+}
+
+impl RouteClass {
+    /// Classifies a request by its matched route. `looks_up_projects` tells whether project IDs are
+    /// looked up on Curseforge before redirecting, and is only called for `/{key}`.
+    fn of(route: &str, path: &str, looks_up_projects: impl FnOnce() -> bool) -> Self {
+        match route {
+            "/f/{file_id}"
+            | "/fp/{fingerprint}"
+            | "/api/v1/resolve"
+            | "/api/v1/manifest"
+            | "/api/v1/credits"
+            | "/api/v1/fingerprints"
+            | "/api/v1/files/{file_id}/hashes"
+            | "/api/v1/files/{file_id}/verify" => RouteClass::Lookup,
+            // numeric keys are project IDs, everything else is an alias that may need a lookup
+            "/{key}" if !path[1..].bytes().all(|it| it.is_ascii_digit()) || looks_up_projects() => {
+                RouteClass::Lookup
+            }
+            _ => RouteClass::Redirect,
+        }
+    }
+}
+
+#[derive(Deserialize, Copy, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Quota {
+    /// Maximum number of requests that can be made in a single burst.
+    pub burst: u32,
+    /// Sustained number of requests allowed per minute.
+    pub per_minute: u32,
+}
+
+impl Quota {
+    fn refill_per_second(&self) -> f64 {
+        f64::from(self.per_minute) / 60.0
+    }
+}
+
+/// The client and route class a request is rate limited as, available as a request extension so
+/// that handlers can [charge](RateLimiter::charge) for work that depends on the request's content.
+#[derive(Copy, Clone, Debug)]
+pub(crate) struct RateLimited {
+    client: IpAddr,
+    class: RouteClass,
+}
+
+/// Rejects a request whose client ran out of tokens.
+pub(crate) struct TooManyRequests {
+    retry_after: u64,
+}
+
+impl IntoResponse for TooManyRequests {
+    fn into_response(self) -> Response {
+        (
+            StatusCode::TOO_MANY_REQUESTS,
+            [(RETRY_AFTER, self.retry_after.to_string())],
+        )
+            .into_response()
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+pub(crate) struct RateLimiter {
+    enabled: bool,
+    quotas: HashMap<RouteClass, Quota>,
+    buckets: Mutex<HashMap<(IpAddr, RouteClass), Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(quotas: HashMap<RouteClass, Quota>) -> Self {
+        RateLimiter {
+            enabled: true,
+            quotas,
+            buckets: Mutex::default(),
+        }
+    }
+
+    pub fn disabled() -> Self {
+        RateLimiter {
+            enabled: false,
+            quotas: HashMap::new(),
+            buckets: Mutex::default(),
+        }
+    }
+
+    /// Takes a token from the client's bucket for the given route class.
+    ///
+    /// Returns the number of seconds until the next token becomes available if the bucket is empty.
+    pub fn acquire(&self, client: IpAddr, class: RouteClass) -> Result<(), u64> {
+        self.acquire_many(client, class, 1)
+    }
+
+    /// Takes a number of tokens at once. Costs above a full burst are taken from a full bucket,
+    /// which then goes into debt for the rest, so that they are charged in full but can still be
+    /// served eventually.
+    ///
+    /// Returns the number of seconds until enough tokens become available if there are too few.
+    pub fn acquire_many(&self, client: IpAddr, class: RouteClass, tokens: u32) -> Result<(), u64> {
+        let Some(quota) = self.quotas.get(&class).copied() else {
+            return Ok(());
+        };
+        let cost = f64::from(tokens);
+        let required = f64::from(tokens.min(quota.burst));
+
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().expect("rate limit buckets poisoned");
+
+        if buckets.len() >= PRUNE_THRESHOLD {
+            Self::prune(&mut buckets, &self.quotas, now);
+        }
+
+        let bucket = buckets.entry((client, class)).or_insert(Bucket {
+            tokens: f64::from(quota.burst),
+            updated: now,
+        });
+
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens =
+            (bucket.tokens + elapsed * quota.refill_per_second()).min(f64::from(quota.burst));
+        bucket.updated = now;
+
+        if bucket.tokens >= required {
+            bucket.tokens -= cost;
+            return Ok(());
+        }
+
+        let missing = required - bucket.tokens;
+        Err((missing / quota.refill_per_second()).ceil() as u64)
+    }
+
+    /// Charges a request additional tokens, e.g. one per Curseforge API call of a bulk lookup.
+    pub fn charge(
+        &self,
+        limited: Option<&RateLimited>,
+        tokens: usize,
+    ) -> Result<(), TooManyRequests> {
+        let Some(limited) = limited.filter(|_| self.enabled && tokens > 0) else {
+            return Ok(());
+        };
+
+        let tokens = u32::try_from(tokens).unwrap_or(u32::MAX);
+        self.acquire_many(limited.client, limited.class, tokens)
+            .map_err(|retry_after| {
+                tracing::debug!(
+                    "Rate limited {client} for {tokens} tokens of {class:?} routes",
+                    client = limited.client,
+                    class = limited.class
+                );
+                TooManyRequests { retry_after }
+            })
+    }
+
+    /// Drops all buckets that have been idle long enough to be completely refilled.
+    fn prune(
+        buckets: &mut HashMap<(IpAddr, RouteClass), Bucket>,
+        quotas: &HashMap<RouteClass, Quota>,
+        now: Instant,
+    ) {
+        buckets.retain(|(_, class), bucket| {
+            let Some(quota) = quotas.get(class) else {
+                return false;
+            };
+            let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+            bucket.tokens + elapsed * quota.refill_per_second() < f64::from(quota.burst)
+        });
+    }
+}
+
+pub(crate) fn init(config: &Config) -> RateLimiter {
+    if !config.rate_limit.enabled {
+        tracing::warn!("Rate limiting is disabled");
+        return RateLimiter::disabled();
+    }
+
+    let quotas = HashMap::from([
+        (RouteClass::Redirect, config.rate_limit.redirect),
+        (RouteClass::Lookup, config.rate_limit.lookup),
+    ]);
+
+    RateLimiter::new(quotas)
+}
+
+pub(crate) async fn limit_requests(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let limiter = &state.rate_limit;
+    if !limiter.enabled || EXEMPT_PATHS.contains(&req.uri().path()) {
+        return next.run(req).await;
+    }
+
+    let host = req.extensions().get::<Arc<HostSettings>>();
+    let class = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|route| {
+            RouteClass::of(route.as_str(), req.uri().path(), || {
+                host.is_some_and(|host| {
+                    resolve::looks_up_projects(&state, host).unwrap_or_else(|err| {
+                        tracing::error!("Unable to check blocklist: {err:#}");
+                        true
+                    })
+                })
+            })
+        })
+        .unwrap_or(RouteClass::Redirect);
+    let client = state.runtime.load().http.trusted_proxies.client_ip(&req);
+
+    match limiter.acquire(client, class) {
+        Ok(()) => {
+            req.extensions_mut().insert(RateLimited { client, class });
+            next.run(req).await
+        }
+        Err(retry_after) => {
+            tracing::debug!("Rate limited {client} for {class:?} routes");
+            TooManyRequests { retry_after }.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::config::{BlockEntry, HostConfig};
+    use crate::forwarded::HostPattern;
+    use crate::rate_limit::{Quota, RateLimiter, RouteClass};
+    use crate::web::test::new_test_server_with;
+    use reqwest::StatusCode;
+    use std::collections::HashMap;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn should_class_routes() {
+        assert_eq!(
+            RouteClass::of("/{key}", "/911456", || false),
+            RouteClass::Redirect
+        );
+        assert_eq!(
+            RouteClass::of("/{key}", "/911456", || true),
+            RouteClass::Lookup
+        );
+        assert_eq!(
+            RouteClass::of("/{key}", "/jei", || false),
+            RouteClass::Lookup
+        );
+        assert_eq!(
+            RouteClass::of("/f/{file_id}", "/f/6774233", || false),
+            RouteClass::Lookup
+        );
+    }
+
+    #[test]
+    fn should_limit_per_client_and_class() {
+        let limiter = RateLimiter::new(HashMap::from([(
+            RouteClass::Lookup,
+            Quota {
+                burst: 2,
+                per_minute: 6,
+            },
+        )]));
+        let client = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+        let other = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 8));
+
+        assert_eq!(limiter.acquire(client, RouteClass::Lookup), Ok(()));
+        assert_eq!(limiter.acquire(client, RouteClass::Lookup), Ok(()));
+        assert_eq!(limiter.acquire(client, RouteClass::Lookup), Err(10));
+        assert_eq!(limiter.acquire(other, RouteClass::Lookup), Ok(()));
+        assert_eq!(limiter.acquire(client, RouteClass::Redirect), Ok(()));
+    }
+
+    #[test]
+    fn should_charge_costs_above_the_burst_in_full() {
+        let limiter = RateLimiter::new(HashMap::from([(
+            RouteClass::Lookup,
+            Quota {
+                burst: 4,
+                per_minute: 60,
+            },
+        )]));
+        let client = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 7));
+
+        assert_eq!(limiter.acquire_many(client, RouteClass::Lookup, 3), Ok(()));
+        assert_eq!(limiter.acquire_many(client, RouteClass::Lookup, 2), Err(1));
+        assert_eq!(limiter.acquire(client, RouteClass::Lookup), Ok(()));
+
+        let other = IpAddr::V4(Ipv4Addr::new(203, 0, 113, 8));
+        assert_eq!(limiter.acquire_many(other, RouteClass::Lookup, 100), Ok(()));
+        assert_eq!(limiter.acquire(other, RouteClass::Lookup), Err(97));
+        assert_eq!(
+            limiter.acquire_many(other, RouteClass::Lookup, 100),
+            Err(100)
+        );
+    }
+
+    async_tests_with_env! {
+        async fn should_limit_project_links_on_game_scoped_hosts_as_lookups() -> anyhow::Result<()> {
+            let server = new_test_server_with(|config| {
+                config.rate_limit.lookup = Quota {
+                    burst: 1,
+                    per_minute: 1,
+                };
+                config.hosts.push(HostConfig {
+                    domain: HostPattern::parse("mc.example").unwrap(),
+                    root_redirect: None,
+                    game_id: Some(432),
+                    analytics_host: None,
+                });
+            })
+            .await?;
+
+            for _ in 0..2 {
+                let response = server.get("/911456").add_header("host", "other.example").await;
+                response.assert_status(StatusCode::SEE_OTHER);
+            }
+
+            let response = server.get("/911456").add_header("host", "mc.example").await;
+            assert_ne!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+            let response = server.get("/911457").add_header("host", "mc.example").await;
+            response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+            Ok(())
+        }
+
+        async fn should_limit_project_links_as_lookups_while_authors_are_blocked() -> anyhow::Result<()> {
+            let server = new_test_server_with(|config| {
+                config.rate_limit.lookup = Quota {
+                    burst: 1,
+                    per_minute: 1,
+                };
+                config.blocklist.authors.push(BlockEntry {
+                    id: 1,
+                    reason: None,
+                });
+            })
+            .await?;
+
+            let response = server.get("/911456").await;
+            assert_ne!(response.status_code(), StatusCode::TOO_MANY_REQUESTS);
+            let response = server.get("/911457").await;
+            response.assert_status(StatusCode::TOO_MANY_REQUESTS);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,80 @@
+use crate::analytics;
+use crate::analytics::Analytics;
+use crate::config::{AdminConfig, BlocklistConfig, Config, HostConfig, HttpConfig};
+use crate::web::AppState;
+use anyhow::Context;
+use std::sync::Arc;
+
+/// Settings that can be swapped out at runtime without restarting the process.
+pub(crate) struct RuntimeConfig {
+    pub http: HttpConfig,
+    pub analytics: Analytics,
+    pub admin: AdminConfig,
+    pub blocklist: BlocklistConfig,
+    pub hosts: Vec<HostConfig>,
+}
+
+pub(crate) async fn init(config: &Config) -> anyhow::Result<RuntimeConfig> {
+    let mut http = config.http.clone();
+    for host in &config.hosts {
+        http.allowed_hosts.allow(host.domain.clone());
+    }
+
+    Ok(RuntimeConfig {
+        http,
+        analytics: analytics::init(&config.analytics).await?,
+        admin: config.admin.clone(),
+        blocklist: config.blocklist.clone(),
+        hosts: config.hosts.clone(),
+    })
+}
+
+/// Re-reads the configuration from its original sources and applies the reloadable parts.
+///
+/// The running configuration is left untouched if the new one is invalid.
+pub(crate) async fn reload(state: &AppState) -> anyhow::Result<()> {
+    // prevent concurrent reloads from racing each other
+    let _guard = state.reload_lock.lock().await;
+
+    let config = Config::load(&state.cli).context("Rejected configuration reload")?;
+    let runtime = init(&config)
+        .await
+        .context("Rejected configuration reload")?;
+    state
+        .curseforge
+        .api_keys
+        .reload()
+        .context("Rejected configuration reload")?;
+
+    state.runtime.store(Arc::new(runtime));
+    tracing::info!("Reloaded configuration");
+    Ok(())
+}
+
+/// Reloads the configuration whenever the process receives SIGHUP.
+#[cfg(unix)]
+pub(crate) fn spawn_signal_listener(state: Arc<AppState>) {
+    use tokio::signal::unix::{SignalKind, signal};
+
+    let mut hangup = match signal(SignalKind::hangup()) {
+        Ok(hangup) => hangup,
+        Err(err) => {
+            tracing::error!(
+                "Unable to listen for SIGHUP, configuration reloads are disabled: {err}"
+            );
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while hangup.recv().await.is_some() {
+            tracing::info!("Received SIGHUP, reloading configuration");
+            if let Err(err) = reload(&state).await {
+                tracing::error!("{err:#}");
+            }
+        }
+    });
+}
+
+#[cfg(not(unix))]
+pub(crate) fn spawn_signal_listener(_state: Arc<AppState>) {}
@@ -0,0 +1,464 @@
+use crate::aliases::AliasTarget;
+use crate::blocklist;
+use crate::blocklist::{BlockKind, Blocked};
+use crate::curseforge::mods::{File, Mod, ModLoaderType, UpstreamError};
+use crate::curseforge::{fingerprints, mods};
+use crate::hosts::HostSettings;
+use crate::web::AppState;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Redirect, Response};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What a request resolved to, filled in by handlers so that analytics can report it.
+#[derive(Serialize, Clone, Default, Debug)]
+pub(crate) struct ResolutionContext {
+    pub kind: Option<RedirectKind>,
+    /// The alias the request was made through, if any.
+    pub alias: Option<String>,
+    pub project_id: Option<u64>,
+    pub file_id: Option<u64>,
+    pub game_id: Option<u64>,
+    pub class_id: Option<u64>,
+    /// Whether all Curseforge lookups were answered from the cache.
+    pub cache: Option<CacheStatus>,
+    /// Time spent waiting for the Curseforge API.
+    pub upstream_latency_ms: Option<u64>,
+    /// Why the request could not be resolved, if it failed.
+    pub error: Option<ResolutionError>,
+}
+
+impl ResolutionContext {
+    fn record_project(&mut self, project: &Mod) {
+        self.project_id = Some(project.id);
+        self.game_id = Some(project.game_id);
+        self.class_id = project.class_id;
+    }
+
+    fn record_lookup(&mut self, hit: bool, elapsed: Duration) {
+        if hit {
+            self.cache.get_or_insert(CacheStatus::Hit);
+        } else {
+            self.cache = Some(CacheStatus::Miss);
+            let elapsed = u64::try_from(elapsed.as_millis()).unwrap_or(u64::MAX);
+            *self.upstream_latency_ms.get_or_insert(0) += elapsed;
+        }
+    }
+}
+
+/// A failed resolution, reported to analytics as an exception.
+#[derive(Serialize, Clone, Debug)]
+pub(crate) struct ResolutionError {
+    pub kind: ErrorKind,
+    /// Messages of the error and all of its causes, outermost first.
+    pub chain: Vec<String>,
+    /// Path of the field that could not be decoded, for decode errors.
+    pub decode_path: Option<String>,
+}
+
+impl ResolutionError {
+    pub fn new(err: &anyhow::Error) -> Self {
+        let mut kind = ErrorKind::Internal;
+        let mut decode_path = None;
+        for cause in err.chain() {
+            if let Some(decode) =
+                cause.downcast_ref::<serde_path_to_error::Error<serde_json::Error>>()
+            {
+                kind = ErrorKind::Decode;
+                decode_path = Some(decode.path().to_string());
+                break;
+            }
+            if let Some(upstream) = cause.downcast_ref::<UpstreamError>() {
+                kind = match upstream {
+                    UpstreamError::Status(_) => ErrorKind::Upstream,
+                    UpstreamError::UnexpectedResult(_) => ErrorKind::UnexpectedResult,
+                };
+                break;
+            }
+            if cause.is::<reqwest::Error>() {
+                kind = ErrorKind::Upstream;
+                break;
+            }
+        }
+
+        ResolutionError {
+            kind,
+            chain: err.chain().map(ToString::to_string).collect(),
+            decode_path,
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorKind {
+    /// A Curseforge API response did not match the expected format.
+    Decode,
+    /// The Curseforge API could not be reached or responded with an error.
+    Upstream,
+    /// The Curseforge API responded with something other than what was asked for.
+    UnexpectedResult,
+    Internal,
+}
+
+impl ErrorKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ErrorKind::Decode => "decode",
+            ErrorKind::Upstream => "upstream",
+            ErrorKind::UnexpectedResult => "unexpected_result",
+            ErrorKind::Internal => "internal",
+        }
+    }
+}
+
+/// Handle to the [`ResolutionContext`] of the current request, available as a request extension.
+pub(crate) type SharedResolution = Arc<Mutex<ResolutionContext>>;
+
+#[derive(Serialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum RedirectKind {
+    Project,
+    File,
+    /// The newest file of a project for a game version.
+    Latest,
+}
+
+impl RedirectKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RedirectKind::Project => "project",
+            RedirectKind::File => "file",
+            RedirectKind::Latest => "latest",
+        }
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CacheStatus {
+    Hit,
+    Miss,
+}
+
+impl CacheStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CacheStatus::Hit => "hit",
+            CacheStatus::Miss => "miss",
+        }
+    }
+}
+
+/// Records why a request could not be resolved, so that it is reported along with the page view.
+pub(crate) fn record_error(context: &Mutex<ResolutionContext>, err: &anyhow::Error) {
+    update(context, |it| it.error = Some(ResolutionError::new(err)));
+}
+
+fn update(context: &Mutex<ResolutionContext>, update: impl FnOnce(&mut ResolutionContext)) {
+    update(&mut context.lock().expect("resolution context poisoned"));
+}
+
+/// Outcome of resolving a lookup.
+pub(crate) enum Resolution {
+    Redirect(String),
+    NotFound,
+    Blocked(Blocked),
+}
+
+impl IntoResponse for Resolution {
+    fn into_response(self) -> Response {
+        match self {
+            Resolution::Redirect(url) => Redirect::to(url.as_str()).into_response(),
+            Resolution::NotFound => StatusCode::NOT_FOUND.into_response(),
+            Resolution::Blocked(blocked) => blocked.into_response(),
+        }
+    }
+}
+
+/// Returns where a project ID redirects to. Projects are resolved by Curseforge itself.
+fn project_url(project_id: u64) -> String {
+    format!("https://curseforge.com/projects/{project_id}")
+}
+
+/// Resolves a project ID, which only requires a lookup if the host is restricted to a game or
+/// authors are blocked.
+pub(crate) async fn project(
+    state: &AppState,
+    host: &HostSettings,
+    context: &Mutex<ResolutionContext>,
+    project_id: u64,
+) -> anyhow::Result<Resolution> {
+    update(context, |it| {
+        it.kind = Some(RedirectKind::Project);
+        it.project_id = Some(project_id);
+    });
+
+    if let Some(blocked) = blocklist::check(state, BlockKind::Project, project_id)? {
+        return Ok(Resolution::Blocked(blocked));
+    }
+    if looks_up_projects(state, host)? {
+        let project = get_mod(state, context, project_id).await?;
+        if host.game_id.is_some()
+            && !project
+                .as_ref()
+                .is_some_and(|project| host.allows_game(project.game_id))
+        {
+            return Ok(Resolution::NotFound);
+        }
+        if let Some(project) = &project
+            && let Some(blocked) = blocklist::check_project(state, project)?
+        {
+            return Ok(Resolution::Blocked(blocked));
+        }
+    }
+
+    Ok(Resolution::Redirect(project_url(project_id)))
+}
+
+/// Whether project IDs are looked up on Curseforge before redirecting, rather than redirected to
+/// directly. The rate limiter counts such redirects as lookups.
+pub(crate) fn looks_up_projects(state: &AppState, host: &HostSettings) -> anyhow::Result<bool> {
+    Ok(host.game_id.is_some() || blocklist::blocks_authors(state)?)
+}
+
+/// Looks up where a file ID redirects to, if the file exists and may be served on this host.
+pub(crate) async fn file(
+    state: &AppState,
+    host: &HostSettings,
+    context: &Mutex<ResolutionContext>,
+    file_id: u64,
+) -> anyhow::Result<Resolution> {
+    update(context, |it| {
+        it.kind = Some(RedirectKind::File);
+        it.file_id = Some(file_id);
+    });
+
+    let (project, _) = match find_file(state, host, context, file_id).await? {
+        Ok(found) => found,
+        Err(unavailable) => return Ok(unavailable),
+    };
+
+    Ok(Resolution::Redirect(format!(
+        "{project_url}/files/{file_id}",
+        project_url = project.links.website_url
+    )))
+}
+
+/// Looks up a file and its project if they may be served on this host, otherwise returns how the
+/// request should be answered instead.
+pub(crate) async fn find_file(
+    state: &AppState,
+    host: &HostSettings,
+    context: &Mutex<ResolutionContext>,
+    file_id: u64,
+) -> anyhow::Result<Result<(Mod, File), Resolution>> {
+    if let Some(blocked) = blocklist::check(state, BlockKind::File, file_id)? {
+        return Ok(Err(Resolution::Blocked(blocked)));
+    }
+    let Some((project, file)) = get_file_info(state, context, file_id).await? else {
+        return Ok(Err(Resolution::NotFound));
+    };
+    if !host.allows_game(project.game_id) {
+        return Ok(Err(Resolution::NotFound));
+    }
+    if let Some(blocked) = blocklist::check_project(state, &project)? {
+        return Ok(Err(Resolution::Blocked(blocked)));
+    }
+
+    Ok(Ok((project, file)))
+}
+
+/// Looks up the file with a Curseforge fingerprint and resolves it like its file ID.
+pub(crate) async fn fingerprint(
+    state: &AppState,
+    host: &HostSettings,
+    context: &Mutex<ResolutionContext>,
+    fingerprint: u32,
+) -> anyhow::Result<Resolution> {
+    update(context, |it| it.kind = Some(RedirectKind::File));
+
+    let started = Instant::now();
+    let matches =
+        fingerprints::get_fingerprint_matches(&state.curseforge, vec![fingerprint]).await?;
+    update(context, |it| it.record_lookup(false, started.elapsed()));
+
+    match matches.get(&fingerprint) {
+        Some(matched) => file(state, host, context, matched.id).await,
+        None => Ok(Resolution::NotFound),
+    }
+}
+
+/// Looks up the newest file of a project for a game version and, optionally, a mod loader.
+pub(crate) async fn latest_file(
+    state: &AppState,
+    host: &HostSettings,
+    context: &Mutex<ResolutionContext>,
+    project_id: u64,
+    game_version: &str,
+    mod_loader: Option<&str>,
+) -> anyhow::Result<Resolution> {
+    update(context, |it| {
+        it.kind = Some(RedirectKind::Latest);
+        it.project_id = Some(project_id);
+    });
+
+    let Some(project) = get_mod(state, context, project_id).await? else {
+        return Ok(Resolution::NotFound);
+    };
+    if !host.allows_game(project.game_id) {
+        return Ok(Resolution::NotFound);
+    }
+    if let Some(blocked) = blocklist::check_project(state, &project)? {
+        return Ok(Resolution::Blocked(blocked));
+    }
+
+    let file_id = project
+        .latest_files_indexes
+        .iter()
+        .filter(|index| index.game_version.eq_ignore_ascii_case(game_version))
+        .filter(|index| {
+            mod_loader.is_none_or(|loader| {
+                index
+                    .mod_loader
+                    .as_ref()
+                    .and_then(loader_name)
+                    .is_some_and(|it| it.eq_ignore_ascii_case(loader))
+            })
+        })
+        .map(|index| index.file_id)
+        .max();
+
+    let Some(file_id) = file_id else {
+        return Ok(Resolution::NotFound);
+    };
+    update(context, |it| it.file_id = Some(file_id));
+    if let Some(blocked) = blocklist::check(state, BlockKind::File, file_id)? {
+        return Ok(Resolution::Blocked(blocked));
+    }
+
+    Ok(Resolution::Redirect(format!(
+        "{project_url}/files/{file_id}",
+        project_url = project.links.website_url
+    )))
+}
+
+/// Looks up where an alias redirects to.
+pub(crate) async fn alias(
+    state: &AppState,
+    host: &HostSettings,
+    context: &Mutex<ResolutionContext>,
+    name: &str,
+    target: &AliasTarget,
+) -> anyhow::Result<Resolution> {
+    update(context, |it| it.alias = Some(name.to_string()));
+
+    match target {
+        AliasTarget::Project { project_id } => project(state, host, context, *project_id).await,
+        AliasTarget::File { file_id } => file(state, host, context, *file_id).await,
+        AliasTarget::LatestFile {
+            project_id,
+            game_version,
+            mod_loader,
+        } => {
+            latest_file(
+                state,
+                host,
+                context,
+                *project_id,
+                game_version,
+                mod_loader.as_deref(),
+            )
+            .await
+        }
+    }
+}
+
+/// Looks up a project, recording it along with whether the cache could answer.
+async fn get_mod(
+    state: &AppState,
+    context: &Mutex<ResolutionContext>,
+    project_id: u64,
+) -> anyhow::Result<Option<Mod>> {
+    let hit = state.curseforge.cache.contains_project(project_id);
+    let started = Instant::now();
+    let project = mods::get_mod(&state.curseforge, project_id).await?;
+
+    update(context, |it| {
+        it.record_lookup(hit, started.elapsed());
+        if let Some(project) = &project {
+            it.record_project(project);
+        }
+    });
+    Ok(project)
+}
+
+/// Looks up a file and its project, recording them along with whether the cache could answer.
+async fn get_file_info(
+    state: &AppState,
+    context: &Mutex<ResolutionContext>,
+    file_id: u64,
+) -> anyhow::Result<Option<(Mod, File)>> {
+    let hit = state.curseforge.cache.contains_file_info(file_id);
+    let started = Instant::now();
+    let info = mods::get_file_info(&state.curseforge, file_id).await?;
+
+    update(context, |it| {
+        it.record_lookup(hit, started.elapsed());
+        if let Some((project, _)) = &info {
+            it.record_project(project);
+        }
+    });
+    Ok(info)
+}
+
+fn loader_name(loader: &ModLoaderType) -> Option<String> {
+    serde_json::to_value(loader)
+        .ok()?
+        .as_str()
+        .map(ToString::to_string)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::curseforge::mods::{Mod, UpstreamError};
+    use crate::resolve::{CacheStatus, ErrorKind, ResolutionContext, ResolutionError};
+    use crate::util::from_json_value;
+    use anyhow::Context;
+    use std::time::Duration;
+
+    #[test]
+    fn should_record_lookups() {
+        let mut context = ResolutionContext::default();
+        context.record_lookup(true, Duration::from_millis(1));
+        assert_eq!(context.cache, Some(CacheStatus::Hit));
+        assert_eq!(context.upstream_latency_ms, None);
+
+        context.record_lookup(false, Duration::from_millis(120));
+        context.record_lookup(true, Duration::from_millis(1));
+        assert_eq!(context.cache, Some(CacheStatus::Miss));
+        assert_eq!(context.upstream_latency_ms, Some(120));
+    }
+
+    #[test]
+    fn should_classify_errors() {
+        let err = from_json_value::<Mod>(&serde_json::json!({ "id": "not a number" }))
+            .context("Unable to decode project 1")
+            .err()
+            .expect("project should not decode");
+        let error = ResolutionError::new(&err);
+        assert_eq!(error.kind, ErrorKind::Decode);
+        assert_eq!(error.decode_path.as_deref(), Some("id"));
+        assert_eq!(error.chain.len(), 2);
+        assert_eq!(error.chain[0], "Unable to decode project 1");
+
+        let err = anyhow::Error::new(UpstreamError::UnexpectedResult("2 files".to_string()));
+        let error = ResolutionError::new(&err.context("lookup"));
+        assert_eq!(error.kind, ErrorKind::UnexpectedResult);
+
+        let error = ResolutionError::new(&anyhow::anyhow!("disk full"));
+        assert_eq!(error.kind, ErrorKind::Internal);
+        assert_eq!(error.decode_path, None);
+    }
+}
@@ -0,0 +1,310 @@
+use crate::config::StatsConfig;
+use crate::resolve::ResolutionContext;
+use crate::web::AppState;
+use chrono::{NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// Referrer domains tracked per day and link, further ones are counted as [`OTHER_REFERRERS`].
+const MAX_REFERRERS: usize = 50;
+const OTHER_REFERRERS: &str = "other";
+const DIRECT: &str = "direct";
+
+/// A link whose clicks are counted.
+#[derive(Serialize, Clone, Eq, PartialEq, Hash, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub(crate) enum StatsTarget {
+    Project { project_id: u64 },
+    File { file_id: u64 },
+    Alias { name: String },
+}
+
+impl Display for StatsTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StatsTarget::Project { project_id } => write!(f, "project:{project_id}"),
+            StatsTarget::File { file_id } => write!(f, "file:{file_id}"),
+            StatsTarget::Alias { name } => write!(f, "alias:{name}"),
+        }
+    }
+}
+
+/// Clicks on a link during one day (UTC).
+#[derive(Serialize, Deserialize, Clone, Default, Debug)]
+pub(crate) struct ClickBucket {
+    pub clicks: u64,
+    /// Clicks by crawlers, link preview bots and scripts, included in `clicks`.
+    pub bots: u64,
+    /// Clicks by referrer domain, `direct` if there was none.
+    pub referrers: BTreeMap<String, u64>,
+}
+
+impl ClickBucket {
+    fn record(&mut self, bot: bool, referrer_host: Option<&str>) {
+        self.add_referrer(referrer_host.unwrap_or(DIRECT), 1);
+        self.clicks += 1;
+        if bot {
+            self.bots += 1;
+        }
+    }
+
+    pub fn merge(&mut self, other: &ClickBucket) {
+        self.clicks += other.clicks;
+        self.bots += other.bots;
+        for (referrer, clicks) in &other.referrers {
+            self.add_referrer(referrer, *clicks);
+        }
+    }
+
+    fn add_referrer(&mut self, referrer: &str, clicks: u64) {
+        let referrer =
+            if self.referrers.contains_key(referrer) || self.referrers.len() < MAX_REFERRERS {
+                referrer
+            } else {
+                OTHER_REFERRERS
+            };
+        *self.referrers.entry(referrer.to_string()).or_default() += clicks;
+    }
+}
+
+/// Aggregates clicks in memory, so that the store is only written to periodically.
+pub(crate) struct ClickCounter {
+    enabled: bool,
+    pending: Mutex<HashMap<(StatsTarget, NaiveDate), ClickBucket>>,
+}
+
+impl ClickCounter {
+    pub fn new(config: &StatsConfig) -> Self {
+        ClickCounter {
+            enabled: config.enabled,
+            pending: Mutex::default(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    /// Counts a click on every link a successful redirect went through.
+    pub fn record(&self, resolution: &ResolutionContext, bot: bool, referrer_host: Option<&str>) {
+        if !self.enabled {
+            return;
+        }
+
+        let targets = [
+            resolution
+                .alias
+                .clone()
+                .map(|name| StatsTarget::Alias { name }),
+            resolution
+                .project_id
+                .map(|project_id| StatsTarget::Project { project_id }),
+            resolution
+                .file_id
+                .map(|file_id| StatsTarget::File { file_id }),
+        ];
+
+        let today = Utc::now().date_naive();
+        let mut pending = self.pending.lock().expect("click counter poisoned");
+        for target in targets.into_iter().flatten() {
+            pending
+                .entry((target, today))
+                .or_default()
+                .record(bot, referrer_host);
+        }
+    }
+
+    /// Clicks that haven't been written to the store yet.
+    pub fn pending(&self, target: &StatsTarget) -> Vec<(NaiveDate, ClickBucket)> {
+        let pending = self.pending.lock().expect("click counter poisoned");
+        pending
+            .iter()
+            .filter(|((it, _), _)| it == target)
+            .map(|((_, date), bucket)| (*date, bucket.clone()))
+            .collect()
+    }
+
+    /// Adds all pending clicks to the store.
+    pub fn flush(&self, state: &AppState) -> anyhow::Result<()> {
+        let pending = std::mem::take(&mut *self.pending.lock().expect("click counter poisoned"));
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        let result = state.store.add_clicks(&pending);
+        if result.is_err() {
+            // keep the clicks around for the next attempt
+            let mut current = self.pending.lock().expect("click counter poisoned");
+            for (key, bucket) in pending {
+                current.entry(key).or_default().merge(&bucket);
+            }
+        }
+        result
+    }
+}
+
+/// Periodically writes pending clicks to the store and, once a day, deletes clicks older than the
+/// retention period.
+pub(crate) fn spawn_flusher(state: Arc<AppState>, config: &StatsConfig) {
+    if !config.enabled {
+        return;
+    }
+
+    let interval = Duration::from_secs(config.flush_interval_secs);
+    let retention = chrono::Duration::days(i64::from(config.retention_days));
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        let mut pruned_on = None;
+        loop {
+            interval.tick().await;
+            let today = Utc::now().date_naive();
+            let prune_before = (pruned_on != Some(today)).then(|| today - retention);
+            pruned_on = Some(today);
+
+            let state = state.clone();
+            let result = tokio::task::spawn_blocking(move || {
+                if let Err(err) = state.clicks.flush(&state) {
+                    tracing::error!("Unable to store click statistics: {err:#}");
+                }
+                if let Some(before) = prune_before {
+                    match state.store.prune_clicks(before) {
+                        Ok(0) => {}
+                        Ok(pruned) => {
+                            tracing::info!(
+                                "Deleted {pruned} click counter(s) from before {before}"
+                            );
+                        }
+                        Err(err) => {
+                            tracing::error!("Unable to delete old click statistics: {err:#}")
+                        }
+                    }
+                }
+            })
+            .await;
+            if let Err(err) = result {
+                tracing::error!("Unable to store click statistics: {err}");
+            }
+        }
+    });
+}
+
+/// Clicks on a link over a range of days.
+#[derive(Serialize)]
+pub(crate) struct LinkStats {
+    pub target: StatsTarget,
+    pub from: NaiveDate,
+    pub to: NaiveDate,
+    pub clicks: u64,
+    pub bots: u64,
+    /// Every day of the range, including those without clicks.
+    pub days: Vec<DayStats>,
+    pub referrers: BTreeMap<String, u64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct DayStats {
+    pub date: NaiveDate,
+    pub clicks: u64,
+    pub bots: u64,
+}
+
+/// Collects the stored and pending clicks on a link for the last `days` days, including today.
+pub(crate) fn link_stats(
+    state: &AppState,
+    target: StatsTarget,
+    days: u32,
+) -> anyhow::Result<LinkStats> {
+    let to = Utc::now().date_naive();
+    let from = to - chrono::Duration::days(i64::from(days.max(1)) - 1);
+
+    let mut buckets: BTreeMap<NaiveDate, ClickBucket> = BTreeMap::new();
+    let stored = state.store.clicks(&target, from, to)?;
+    for (date, bucket) in stored.into_iter().chain(state.clicks.pending(&target)) {
+        if (from..=to).contains(&date) {
+            buckets.entry(date).or_default().merge(&bucket);
+        }
+    }
+
+    let mut total = ClickBucket::default();
+    for bucket in buckets.values() {
+        total.merge(bucket);
+    }
+
+    Ok(LinkStats {
+        target,
+        from,
+        to,
+        clicks: total.clicks,
+        bots: total.bots,
+        days: from
+            .iter_days()
+            .take_while(|date| *date <= to)
+            .map(|date| {
+                let bucket = buckets.get(&date);
+                DayStats {
+                    date,
+                    clicks: bucket.map_or(0, |it| it.clicks),
+                    bots: bucket.map_or(0, |it| it.bots),
+                }
+            })
+            .collect(),
+        referrers: total.referrers,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use crate::config::StoreConfig;
+    use crate::stats::{ClickBucket, MAX_REFERRERS, StatsTarget};
+    use crate::store::Store;
+    use chrono::NaiveDate;
+    use std::collections::HashMap;
+
+    #[test]
+    fn should_cap_referrers() {
+        let mut bucket = ClickBucket::default();
+        for i in 0..MAX_REFERRERS + 5 {
+            bucket.record(false, Some(&format!("site{i}.example")));
+        }
+        bucket.record(true, None);
+
+        assert_eq!(bucket.clicks, MAX_REFERRERS as u64 + 6);
+        assert_eq!(bucket.bots, 1);
+        assert_eq!(bucket.referrers.len(), MAX_REFERRERS + 1);
+        assert_eq!(bucket.referrers["other"], 6);
+    }
+
+    #[test]
+    fn should_prune_old_clicks() -> anyhow::Result<()> {
+        let store = Store::open(&StoreConfig::default())?;
+        let target = StatsTarget::Project { project_id: 911456 };
+        let day = |day| NaiveDate::from_ymd_opt(2026, 10, day).expect("valid date");
+        let mut bucket = ClickBucket::default();
+        bucket.record(false, None);
+        store.add_clicks(&HashMap::from([
+            ((target.clone(), day(1)), bucket.clone()),
+            ((target.clone(), day(2)), bucket.clone()),
+            (
+                (
+                    StatsTarget::Alias {
+                        name: "a:b".to_string(),
+                    },
+                    day(1),
+                ),
+                bucket,
+            ),
+        ]))?;
+
+        assert_eq!(store.prune_clicks(day(2))?, 2);
+        let remaining: Vec<_> = store
+            .clicks(&target, day(1), day(2))?
+            .into_iter()
+            .map(|(date, _)| date)
+            .collect();
+        assert_eq!(remaining, [day(2)]);
+        Ok(())
+    }
+}
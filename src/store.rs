@@ -0,0 +1,283 @@
+use crate::aliases::{Alias, AliasTarget};
+use crate::blocklist::{Block, BlockKind};
+use crate::config::StoreConfig;
+use crate::stats::{ClickBucket, StatsTarget};
+use crate::util::{CheckStatus, HealthCheck};
+use anyhow::Context;
+use chrono::{NaiveDate, Utc};
+use redb::backends::InMemoryBackend;
+use redb::{Database, ReadableDatabase, ReadableTable, ReadableTableMetadata, TableDefinition};
+use std::collections::HashMap;
+
+/// Aliases by name, stored as JSON.
+const ALIASES: TableDefinition<&str, &[u8]> = TableDefinition::new("aliases");
+/// Blocklist entries by `kind:id`, stored as JSON.
+const BLOCKS: TableDefinition<&str, &[u8]> = TableDefinition::new("blocks");
+/// Daily click counters by `target:date`, stored as JSON.
+const CLICKS: TableDefinition<&str, &[u8]> = TableDefinition::new("clicks");
+
+/// Embedded database for state that has to survive restarts.
+pub(crate) struct Store {
+    db: Database,
+    persistent: bool,
+}
+
+impl Store {
+    pub fn open(config: &StoreConfig) -> anyhow::Result<Self> {
+        let (db, persistent) = match &config.path {
+            Some(path) => (
+                Database::create(path)
+                    .with_context(|| format!("Unable to open store at {}", path.display()))?,
+                true,
+            ),
+            None => {
+                tracing::warn!(
+                    "No store path configured, aliases and blocks will be lost on restart"
+                );
+                (
+                    Database::builder().create_with_backend(InMemoryBackend::new())?,
+                    false,
+                )
+            }
+        };
+
+        // create all tables up front, so that reads never have to deal with missing tables
+        let txn = db.begin_write()?;
+        txn.open_table(ALIASES)?;
+        txn.open_table(BLOCKS)?;
+        txn.open_table(CLICKS)?;
+        txn.commit()?;
+
+        Ok(Store { db, persistent })
+    }
+
+    pub fn health_check(&self) -> HealthCheck {
+        let result = self
+            .db
+            .begin_read()
+            .map_err(anyhow::Error::from)
+            .and_then(|txn| Ok(txn.open_table(ALIASES)?.len()?));
+
+        match result {
+            Ok(aliases) if self.persistent => {
+                HealthCheck::new(CheckStatus::Ok, false).with_message(format!("{aliases} aliases"))
+            }
+            Ok(aliases) => HealthCheck::new(CheckStatus::Degraded, false)
+                .with_message(format!("in-memory, {aliases} aliases")),
+            Err(err) => {
+                HealthCheck::new(CheckStatus::Failing, false).with_message(format!("{err:#}"))
+            }
+        }
+    }
+
+    pub fn alias(&self, name: &str) -> anyhow::Result<Option<Alias>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(ALIASES)?;
+        table
+            .get(name)?
+            .map(|value| serde_json::from_slice(value.value()).context("Invalid stored alias"))
+            .transpose()
+    }
+
+    pub fn aliases(&self) -> anyhow::Result<Vec<Alias>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(ALIASES)?;
+        table
+            .iter()?
+            .map(|entry| {
+                let (_, value) = entry?;
+                serde_json::from_slice(value.value()).context("Invalid stored alias")
+            })
+            .collect()
+    }
+
+    /// Creates or updates an alias, returning it along with whether it was newly created.
+    pub fn put_alias(&self, name: &str, target: AliasTarget) -> anyhow::Result<(Alias, bool)> {
+        let txn = self.db.begin_write()?;
+        let result = {
+            let mut table = txn.open_table(ALIASES)?;
+            let existing: Option<Alias> = table
+                .get(name)?
+                .map(|value| serde_json::from_slice(value.value()))
+                .transpose()
+                .context("Invalid stored alias")?;
+
+            let now = Utc::now();
+            let alias = Alias {
+                name: name.to_string(),
+                target,
+                created_at: existing.as_ref().map_or(now, |it| it.created_at),
+                updated_at: now,
+            };
+            table.insert(name, serde_json::to_vec(&alias)?.as_slice())?;
+            (alias, existing.is_none())
+        };
+        txn.commit()?;
+
+        Ok(result)
+    }
+
+    /// Deletes an alias, returning whether it existed.
+    pub fn delete_alias(&self, name: &str) -> anyhow::Result<bool> {
+        let txn = self.db.begin_write()?;
+        let existed = txn.open_table(ALIASES)?.remove(name)?.is_some();
+        txn.commit()?;
+
+        Ok(existed)
+    }
+
+    pub fn block(&self, kind: BlockKind, id: u64) -> anyhow::Result<Option<Block>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(BLOCKS)?;
+        table
+            .get(block_key(kind, id).as_str())?
+            .map(|value| serde_json::from_slice(value.value()).context("Invalid stored block"))
+            .transpose()
+    }
+
+    pub fn blocks(&self) -> anyhow::Result<Vec<Block>> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(BLOCKS)?;
+        table
+            .iter()?
+            .map(|entry| {
+                let (_, value) = entry?;
+                serde_json::from_slice(value.value()).context("Invalid stored block")
+            })
+            .collect()
+    }
+
+    /// Whether there is any block of this kind, found with a range scan over its keys.
+    pub fn has_blocks(&self, kind: BlockKind) -> anyhow::Result<bool> {
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(BLOCKS)?;
+        // keys are `kind:id` and `;` follows `:`, so this covers exactly the keys of the kind
+        let (start, end) = (format!("{kind}:"), format!("{kind};"));
+        Ok(table
+            .range(start.as_str()..end.as_str())?
+            .next()
+            .transpose()?
+            .is_some())
+    }
+
+    /// Creates or updates a block, returning it along with whether it was newly created.
+    pub fn put_block(
+        &self,
+        kind: BlockKind,
+        id: u64,
+        reason: Option<String>,
+    ) -> anyhow::Result<(Block, bool)> {
+        let key = block_key(kind, id);
+        let txn = self.db.begin_write()?;
+        let result = {
+            let mut table = txn.open_table(BLOCKS)?;
+            let existing: Option<Block> = table
+                .get(key.as_str())?
+                .map(|value| serde_json::from_slice(value.value()))
+                .transpose()
+                .context("Invalid stored block")?;
+
+            let block = Block {
+                kind,
+                id,
+                reason,
+                created_at: existing.as_ref().map_or_else(Utc::now, |it| it.created_at),
+            };
+            table.insert(key.as_str(), serde_json::to_vec(&block)?.as_slice())?;
+            (block, existing.is_none())
+        };
+        txn.commit()?;
+
+        Ok(result)
+    }
+
+    /// Returns the daily click counters of a link between two dates, inclusive.
+    pub fn clicks(
+        &self,
+        target: &StatsTarget,
+        from: NaiveDate,
+        to: NaiveDate,
+    ) -> anyhow::Result<Vec<(NaiveDate, ClickBucket)>> {
+        let (start, end) = (clicks_key(target, from), clicks_key(target, to));
+        let txn = self.db.begin_read()?;
+        let table = txn.open_table(CLICKS)?;
+        table
+            .range(start.as_str()..=end.as_str())?
+            .map(|entry| {
+                let (key, value) = entry?;
+                let date = key
+                    .value()
+                    .rsplit_once(':')
+                    .and_then(|(_, date)| date.parse().ok())
+                    .context("Invalid stored click counter key")?;
+                let bucket = serde_json::from_slice(value.value())
+                    .context("Invalid stored click counter")?;
+                Ok((date, bucket))
+            })
+            .collect()
+    }
+
+    /// Adds clicks to the stored daily counters in a single transaction.
+    pub fn add_clicks(
+        &self,
+        clicks: &HashMap<(StatsTarget, NaiveDate), ClickBucket>,
+    ) -> anyhow::Result<()> {
+        let txn = self.db.begin_write()?;
+        {
+            let mut table = txn.open_table(CLICKS)?;
+            for ((target, date), added) in clicks {
+                let key = clicks_key(target, *date);
+                let mut bucket: ClickBucket = table
+                    .get(key.as_str())?
+                    .map(|value| serde_json::from_slice(value.value()))
+                    .transpose()
+                    .context("Invalid stored click counter")?
+                    .unwrap_or_default();
+                bucket.merge(added);
+                table.insert(key.as_str(), serde_json::to_vec(&bucket)?.as_slice())?;
+            }
+        }
+        txn.commit()?;
+
+        Ok(())
+    }
+
+    /// Deletes all daily click counters from before a date, returning how many were deleted.
+    pub fn prune_clicks(&self, before: NaiveDate) -> anyhow::Result<usize> {
+        let mut pruned = 0;
+        let txn = self.db.begin_write()?;
+        txn.open_table(CLICKS)?.retain(|key, _| {
+            let expired = key
+                .rsplit_once(':')
+                .and_then(|(_, date)| date.parse::<NaiveDate>().ok())
+                .is_some_and(|date| date < before);
+            if expired {
+                pruned += 1;
+            }
+            !expired
+        })?;
+        txn.commit()?;
+
+        Ok(pruned)
+    }
+
+    /// Deletes a block, returning whether it existed.
+    pub fn delete_block(&self, kind: BlockKind, id: u64) -> anyhow::Result<bool> {
+        let txn = self.db.begin_write()?;
+        let existed = txn
+            .open_table(BLOCKS)?
+            .remove(block_key(kind, id).as_str())?
+            .is_some();
+        txn.commit()?;
+
+        Ok(existed)
+    }
+}
+
+fn clicks_key(target: &StatsTarget, date: NaiveDate) -> String {
+    format!("{target}:{date}")
+}
+
+fn block_key(kind: BlockKind, id: u64) -> String {
+    format!("{kind}:{id}")
+}
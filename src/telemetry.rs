@@ -0,0 +1,143 @@
+use anyhow::Context;
+use axum::extract::{MatchedPath, Request};
+use axum::http::{HeaderName, HeaderValue};
+use axum::middleware::Next;
+use axum::response::Response;
+use opentelemetry::trace::TracerProvider;
+use opentelemetry_otlp::SpanExporter;
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::env;
+use tracing::{Instrument, field};
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use uuid::Uuid;
+
+const SERVICE_NAME: &str = "mods.cf";
+const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Keeps the OpenTelemetry exporter alive, flushing pending spans when dropped.
+pub struct TelemetryGuard {
+    tracer_provider: Option<SdkTracerProvider>,
+}
+
+impl Drop for TelemetryGuard {
+    fn drop(&mut self) {
+        if let Some(provider) = self.tracer_provider.take()
+            && let Err(err) = provider.shutdown()
+        {
+            // the fmt layer of the global subscriber outlives the provider, so this is still logged
+            tracing::error!("Unable to flush OpenTelemetry spans: {err:#}");
+        }
+    }
+}
+
+/// Sets up the global `tracing` subscriber.
+///
+/// Logs are written to stderr as plain text by default, or as JSON if `LOG_FORMAT=json`.
+/// Spans are additionally exported via OTLP/HTTP if `OTEL_EXPORTER_OTLP_ENDPOINT` is set.
+pub fn init() -> anyhow::Result<TelemetryGuard> {
+    let json = match env::var("LOG_FORMAT").ok().as_deref() {
+        None | Some("text") => false,
+        Some("json") => true,
+        Some(other) => anyhow::bail!("Unsupported LOG_FORMAT '{other}', expected 'text' or 'json'"),
+    };
+
+    let tracer_provider = match env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok() {
+        Some(_) => {
+            let exporter = SpanExporter::builder()
+                .with_http()
+                .build()
+                .context("Unable to create OTLP span exporter")?;
+            Some(
+                SdkTracerProvider::builder()
+                    .with_batch_exporter(exporter)
+                    .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+                    .build(),
+            )
+        }
+        None => None,
+    };
+
+    tracing_subscriber::registry()
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(json.then(|| {
+            tracing_subscriber::fmt::layer()
+                .with_writer(std::io::stderr)
+                .json()
+                .with_current_span(true)
+                .with_span_list(false)
+        }))
+        .with((!json).then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr)))
+        .with(tracer_provider.as_ref().map(|provider| {
+            tracing_opentelemetry::layer().with_tracer(provider.tracer(SERVICE_NAME))
+        }))
+        .try_init()
+        .context("Unable to initialize tracing")?;
+
+    if tracer_provider.is_some() {
+        tracing::info!("OpenTelemetry span export enabled");
+    }
+
+    Ok(TelemetryGuard { tracer_provider })
+}
+
+/// Wraps each request in a span carrying a request ID.
+///
+/// The ID is taken from an incoming `X-Request-Id` header if present and generated otherwise.
+/// It is echoed back to the client in the response.
+pub(crate) async fn trace_requests(req: Request, next: Next) -> Response {
+    let request_id = req
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|it| it.to_str().ok())
+        .filter(|it| !it.is_empty() && it.len() <= 128)
+        .map(ToString::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string());
+
+    let route = req
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        method = %req.method(),
+        path = %req.uri().path(),
+        route = route,
+        status = field::Empty,
+    );
+
+    let mut response = next.run(req).instrument(span.clone()).await;
+    span.record("status", response.status().as_u16());
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::test::new_test_server;
+
+    async_tests_with_env! {
+        async fn should_propagate_request_id() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server
+                .get("/health")
+                .add_header("x-request-id", "test-request-id")
+                .await;
+            response.assert_header("x-request-id", "test-request-id");
+
+            let response = server.get("/health").await;
+            assert!(response.maybe_header("x-request-id").is_some());
+            Ok(())
+        }
+    }
+}
@@ -3,9 +3,11 @@ use axum::Json;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use posthog_rs::Event;
 use serde::Serialize;
 use serde::de::DeserializeOwned;
+use std::collections::BTreeMap;
 
 pub(crate) fn default_true() -> bool {
     true
@@ -15,6 +17,27 @@ pub(crate) fn default_true() -> bool {
 pub(crate) struct HealthResponse {
     pub status: u16,
     pub message: Option<String>,
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub checks: BTreeMap<&'static str, HealthCheck>,
+}
+
+impl HealthResponse {
+    /// Builds a response that is only successful if none of the critical checks are failing.
+    pub fn from_checks(checks: BTreeMap<&'static str, HealthCheck>) -> Self {
+        let ready = checks
+            .values()
+            .all(|check| !check.critical || check.status != CheckStatus::Failing);
+        let status = if ready {
+            StatusCode::OK
+        } else {
+            StatusCode::SERVICE_UNAVAILABLE
+        };
+
+        HealthResponse {
+            checks,
+            ..HealthResponse::from(status)
+        }
+    }
 }
 
 impl From<StatusCode> for HealthResponse {
@@ -22,13 +45,56 @@ impl From<StatusCode> for HealthResponse {
         HealthResponse {
             status: value.as_u16(),
             message: value.canonical_reason().map(|it| it.to_string()),
+            checks: BTreeMap::new(),
         }
     }
 }
 
 impl IntoResponse for HealthResponse {
     fn into_response(self) -> Response {
-        Json(self).into_response()
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        (status, Json(self)).into_response()
+    }
+}
+
+#[derive(Serialize, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CheckStatus {
+    Ok,
+    Degraded,
+    Failing,
+    Disabled,
+}
+
+#[derive(Serialize, Clone)]
+pub(crate) struct HealthCheck {
+    pub status: CheckStatus,
+    /// Whether a failure of this check should mark the service as not ready.
+    pub critical: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checked_at: Option<DateTime<Utc>>,
+}
+
+impl HealthCheck {
+    pub fn new(status: CheckStatus, critical: bool) -> Self {
+        HealthCheck {
+            status,
+            critical,
+            message: None,
+            checked_at: None,
+        }
+    }
+
+    pub fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = Some(message.into());
+        self
+    }
+
+    pub fn checked_at(mut self, time: DateTime<Utc>) -> Self {
+        self.checked_at = Some(time);
+        self
     }
 }
 
@@ -51,6 +117,11 @@ impl reqwest::Response {
     }
 }
 
+/// Decodes an already parsed JSON value, reporting the path of any field that failed to decode.
+pub(crate) fn from_json_value<T: DeserializeOwned>(value: &serde_json::Value) -> anyhow::Result<T> {
+    Ok(serde_path_to_error::deserialize(value)?)
+}
+
 #[extension(pub(crate) trait StatusExt)]
 impl StatusCode {
     fn is_success_or_redirect(&self) -> bool {
@@ -58,11 +129,43 @@ impl StatusCode {
     }
 }
 
+/// Escapes text for use in HTML element content and attribute values.
+pub(crate) fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        match char {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
+/// Escapes text for use in Markdown, including table cells and link texts.
+pub(crate) fn escape_markdown(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for char in value.chars() {
+        match char {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '|' | '#' => {
+                escaped.push('\\');
+                escaped.push(char);
+            }
+            '\n' | '\r' => escaped.push(' '),
+            _ => escaped.push(char),
+        }
+    }
+    escaped
+}
+
 #[extension(pub(crate) trait CaptureEventProperties)]
 impl Event {
     fn with<K: Into<String>, V: Serialize>(mut self, key: K, value: V) -> Self {
         if let Err(err) = self.insert_prop(key, value) {
-            log::error!("Unable to set event error context: {err:#}");
+            tracing::error!("Unable to set event error context: {err:#}");
         }
 
         self
@@ -1,74 +1,194 @@
-use crate::analytics::Analytics;
+use crate::analytics::privacy::DailySalt;
+use crate::analytics::{AnalyticsQueue, AnalyticsWorker};
+use crate::config::{Cli, Config};
 use crate::curseforge::CurseforgeState;
-use crate::util::HealthResponse;
-use crate::{analytics, curseforge};
-use anyhow::Context;
-use axum::http::StatusCode;
+use crate::hosts::HostSettings;
+use crate::metrics::Metrics;
+use crate::rate_limit::RateLimiter;
+use crate::reload::RuntimeConfig;
+use crate::stats::ClickCounter;
+use crate::store::Store;
+use crate::{analytics, curseforge, hosts, metrics, rate_limit, reload, telemetry};
+use arc_swap::ArcSwap;
+use axum::extract::DefaultBodyLimit;
 use axum::response::Redirect;
-use axum::routing::get;
-use axum::{Router, middleware};
-use std::env;
+use axum::routing::{get, post};
+use axum::{Extension, Router, middleware};
 use std::sync::Arc;
-use url::Url;
+use tokio::sync::Mutex;
 
+mod admin;
+mod batch;
+mod credits;
 mod files;
+mod fingerprints;
+mod hashes;
+mod health;
+mod lookup;
+mod manifest;
 pub mod projects;
+mod stats;
 
 pub(crate) struct AppState {
-    pub http: HttpConfig,
-    pub analytics: Analytics,
+    /// Command line flags the process was started with, used to reload the configuration.
+    pub cli: Cli,
+    pub runtime: ArcSwap<RuntimeConfig>,
+    pub reload_lock: Mutex<()>,
     pub curseforge: CurseforgeState,
+    pub store: Store,
+    pub rate_limit: RateLimiter,
+    pub analytics_queue: AnalyticsQueue,
+    pub visitor_salt: DailySalt,
+    pub clicks: ClickCounter,
+    pub metrics: Arc<Metrics>,
 }
 
-pub(crate) struct HttpConfig {
-    pub frontend_url: Url,
+/// The router together with the state that has to be cleaned up when shutting down.
+pub struct App {
+    pub router: Router,
+    state: Arc<AppState>,
+    analytics_worker: AnalyticsWorker,
 }
 
-pub async fn init_router(enable_analytics: bool) -> anyhow::Result<Router> {
+impl App {
+    /// Releases resources once the server has stopped accepting and serving requests.
+    pub async fn shutdown(self) {
+        self.analytics_worker.flush().await;
+
+        let state = self.state;
+        let result = tokio::task::spawn_blocking(move || {
+            if let Err(err) = state.clicks.flush(&state) {
+                tracing::error!("Unable to store click statistics: {err:#}");
+            }
+            state.curseforge.cache.persist()
+        })
+        .await;
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => tracing::error!("Unable to persist cache: {err:#}"),
+            Err(err) => tracing::error!("Unable to persist cache: {err}"),
+        }
+    }
+}
+
+pub async fn init_app(cli: &Cli, config: &Config) -> anyhow::Result<App> {
+    let metrics = Arc::new(Metrics::new()?);
+    let (analytics_queue, analytics_events) =
+        AnalyticsQueue::new(&config.analytics, metrics.clone());
     let app_data = Arc::new(AppState {
-        http: init_http()?,
-        analytics: analytics::init(enable_analytics).await?,
-        curseforge: curseforge::init()?,
+        cli: cli.clone(),
+        runtime: ArcSwap::from_pointee(reload::init(config).await?),
+        reload_lock: Mutex::default(),
+        curseforge: curseforge::init(config, metrics.clone())?,
+        store: Store::open(&config.store)?,
+        rate_limit: rate_limit::init(config),
+        analytics_queue,
+        visitor_salt: DailySalt::default(),
+        clicks: ClickCounter::new(&config.stats),
+        metrics,
     });
-    let router = Router::new()
+    curseforge::spawn_key_reloader(app_data.clone(), config);
+    reload::spawn_signal_listener(app_data.clone());
+    crate::stats::spawn_flusher(app_data.clone(), &config.stats);
+
+    let analytics_worker =
+        AnalyticsWorker::spawn(app_data.clone(), &config.analytics, analytics_events);
+
+    Ok(App {
+        router: init_router(app_data.clone()),
+        state: app_data,
+        analytics_worker,
+    })
+}
+
+fn init_router(app_data: Arc<AppState>) -> Router {
+    Router::new()
         .route(
             "/",
-            get(async || Redirect::to("https://www.curseforge.com")),
+            get(async |Extension(host): Extension<Arc<HostSettings>>| {
+                Redirect::to(host.root_redirect.as_str())
+            }),
         )
+        .route("/health", get(health::live))
+        .route("/health/live", get(health::live))
+        .route("/health/ready", get(health::ready))
+        .route("/metrics", get(metrics::export))
+        .route("/{key}", get(projects::project_or_alias))
+        .route("/f/{file_id}", get(files::file_by_id))
+        .route("/fp/{fingerprint}", get(fingerprints::file_by_fingerprint))
+        .route("/{key}/stats", get(stats::project_or_alias_page))
+        .route("/f/{file_id}/stats", get(stats::file_page))
         .route(
-            "/health",
-            get(async || HealthResponse::from(StatusCode::OK)),
+            "/api/v1/stats/projects/{project_id}",
+            get(stats::project_stats),
+        )
+        .route("/api/v1/stats/files/{file_id}", get(stats::file_stats))
+        .route("/api/v1/stats/aliases/{name}", get(stats::alias_stats))
+        .route("/api/v1/resolve", post(batch::resolve))
+        .route(
+            "/api/v1/manifest",
+            post(manifest::mod_list).layer(DefaultBodyLimit::max(manifest::MAX_UPLOAD_SIZE)),
+        )
+        .route(
+            "/api/v1/fingerprints",
+            post(fingerprints::identify).layer(DefaultBodyLimit::max(manifest::MAX_UPLOAD_SIZE)),
+        )
+        .route("/api/v1/files/{file_id}/hashes", get(hashes::hashes))
+        .route(
+            "/api/v1/files/{file_id}/verify",
+            post(hashes::verify).layer(DefaultBodyLimit::max(manifest::MAX_UPLOAD_SIZE)),
+        )
+        .route(
+            "/api/v1/credits",
+            post(credits::credits).layer(DefaultBodyLimit::max(manifest::MAX_UPLOAD_SIZE)),
         )
-        .route("/{project_id}", get(projects::project_by_id))
-        .route("/f/{file_id}", get(files::file_by_id))
         .layer(middleware::from_fn_with_state(
             app_data.clone(),
             analytics::capture_analytics,
         ))
-        .with_state(app_data);
-
-    Ok(router)
-}
-
-fn init_http() -> anyhow::Result<HttpConfig> {
-    let frontend_url = match env::var("FRONTEND_URL").ok() {
-        Some(url) => Url::parse(&url).context("FRONTEND_URL not set to a valid URL")?,
-        None => Url::parse("http://localhost").expect("unable to parse localhost URL"),
-    };
-
-    Ok(HttpConfig { frontend_url })
+        // mounted after the analytics layer, so that admin calls are never captured as pageviews
+        .nest("/admin", admin::router(app_data.clone()))
+        // mounted after the host layer, so that the rate limit can depend on the host's settings
+        .layer(middleware::from_fn_with_state(
+            app_data.clone(),
+            rate_limit::limit_requests,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_data.clone(),
+            hosts::resolve_host,
+        ))
+        .layer(middleware::from_fn_with_state(
+            app_data.clone(),
+            metrics::track_requests,
+        ))
+        .layer(middleware::from_fn(telemetry::trace_requests))
+        .with_state(app_data)
 }
 
 #[cfg(test)]
 pub mod test {
-    use crate::web::init_router;
+    use crate::config::{Cli, Config};
+    use crate::web::init_app;
     use anyhow::Context;
     use axum_test::TestServer;
 
     pub(crate) async fn new_test_server() -> anyhow::Result<TestServer> {
-        let app = init_router(false)
+        new_test_server_with(|_| {}).await
+    }
+
+    /// Creates a test server with the given adjustments applied on top of the default configuration.
+    pub(crate) async fn new_test_server_with(
+        configure: impl FnOnce(&mut Config),
+    ) -> anyhow::Result<TestServer> {
+        let cli = Cli {
+            no_analytics: true,
+            ..Cli::default()
+        };
+        let mut config = Config::load(&cli)?;
+        configure(&mut config);
+        let app = init_app(&cli, &config)
             .await
             .context("Unable to create test server")?;
-        Ok(TestServer::builder().mock_transport().build(app))
+        Ok(TestServer::builder().mock_transport().build(app.router))
     }
 }
@@ -0,0 +1,709 @@
+use crate::aliases;
+use crate::aliases::{Alias, AliasTarget};
+use crate::blocklist::{Block, BlockKind};
+use crate::config::AdminScope;
+use crate::curseforge::cache::{CacheSummary, EntryInfo};
+use crate::curseforge::mods;
+use crate::curseforge::mods::{File, Mod};
+use crate::reload;
+use crate::reload::RuntimeConfig;
+use crate::web::AppState;
+use axum::extract::{Path, Query, Request, State};
+use axum::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use axum::http::{HeaderMap, Method, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post, put};
+use axum::{Extension, Json, Router, middleware};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+#[derive(Serialize)]
+pub(crate) struct AdminResponse {
+    pub status: u16,
+    pub message: String,
+}
+
+impl AdminResponse {
+    fn new(status: StatusCode, message: impl Into<String>) -> Self {
+        AdminResponse {
+            status: status.as_u16(),
+            message: message.into(),
+        }
+    }
+}
+
+impl IntoResponse for AdminResponse {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let mut response = (status, Json(self)).into_response();
+        if status == StatusCode::UNAUTHORIZED {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, "Bearer".parse().expect("valid header"));
+        }
+        response
+    }
+}
+
+/// The authenticated caller of an admin endpoint, available as a request extension.
+#[derive(Clone)]
+pub(crate) struct AdminIdentity {
+    pub name: String,
+    scopes: Vec<AdminScope>,
+}
+
+impl AdminIdentity {
+    fn require(&self, scope: AdminScope) -> Result<(), AdminResponse> {
+        if self.scopes.iter().any(|it| it.grants(scope)) {
+            Ok(())
+        } else {
+            Err(AdminResponse::new(
+                StatusCode::FORBIDDEN,
+                format!("Token '{}' lacks the {scope:?} scope", self.name),
+            ))
+        }
+    }
+}
+
+pub(crate) fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/reload", post(reload))
+        .route("/aliases", get(list_aliases))
+        .route(
+            "/aliases/{name}",
+            get(get_alias).put(put_alias).delete(delete_alias),
+        )
+        .route("/cache", get(cache_summary).delete(purge_cache))
+        .route(
+            "/cache/projects/{project_id}",
+            get(cached_project).delete(purge_project),
+        )
+        .route(
+            "/cache/files/{file_id}",
+            get(cached_file).delete(purge_file),
+        )
+        .route("/blocklist", get(list_blocks))
+        .route(
+            "/blocklist/{kind}/{id}",
+            put(put_block).delete(delete_block),
+        )
+        .layer(middleware::from_fn_with_state(state, authenticate))
+}
+
+/// Resolves the request's bearer token to an admin identity and audit-logs mutating calls.
+///
+/// The admin API pretends not to exist if no tokens are configured.
+async fn authenticate(
+    State(state): State<Arc<AppState>>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let method = req.method().clone();
+    let path = req.uri().path().to_string();
+    let mutating = !matches!(method, Method::GET | Method::HEAD);
+
+    let identity = match identify(&state.runtime.load(), req.headers()) {
+        Ok(identity) => identity,
+        Err(response) => {
+            if mutating && response.status == StatusCode::UNAUTHORIZED.as_u16() {
+                tracing::warn!(target: "audit", %method, path, "Rejected unauthenticated admin call");
+            }
+            return response.into_response();
+        }
+    };
+
+    req.extensions_mut().insert(identity.clone());
+    let response = next.run(req).await;
+
+    if mutating {
+        tracing::info!(
+            target: "audit",
+            actor = identity.name,
+            %method,
+            path,
+            status = response.status().as_u16(),
+            "Admin call"
+        );
+    }
+
+    response
+}
+
+fn identify(runtime: &RuntimeConfig, headers: &HeaderMap) -> Result<AdminIdentity, AdminResponse> {
+    if runtime.admin.tokens.is_empty() {
+        return Err(AdminResponse::new(StatusCode::NOT_FOUND, "Not Found"));
+    }
+
+    let unauthorized =
+        || AdminResponse::new(StatusCode::UNAUTHORIZED, "Missing or invalid admin token");
+    let provided = headers
+        .get(AUTHORIZATION)
+        .and_then(|it| it.to_str().ok())
+        .and_then(|it| it.strip_prefix("Bearer "))
+        .map(str::trim)
+        .filter(|it| !it.is_empty())
+        .ok_or_else(unauthorized)?;
+
+    let hash = hex::encode(Sha256::digest(provided.as_bytes()));
+    runtime
+        .admin
+        .tokens
+        .iter()
+        .find(|token| {
+            constant_time_eq(
+                token.sha256.to_ascii_lowercase().as_bytes(),
+                hash.as_bytes(),
+            )
+        })
+        .map(|token| AdminIdentity {
+            name: token.name.clone(),
+            scopes: token.scopes.clone(),
+        })
+        .ok_or_else(unauthorized)
+}
+
+/// Compares two byte strings without leaking the position of the first mismatch through timing.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Re-reads the configuration and applies it, just like sending SIGHUP to the process.
+async fn reload(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> AdminResponse {
+    if let Err(response) = identity.require(AdminScope::Full) {
+        return response;
+    }
+
+    match reload::reload(&state).await {
+        Ok(()) => AdminResponse::new(StatusCode::OK, "Configuration reloaded"),
+        Err(err) => {
+            tracing::error!("{err:#}");
+            AdminResponse::new(StatusCode::UNPROCESSABLE_ENTITY, format!("{err:#}"))
+        }
+    }
+}
+
+async fn list_aliases(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<Vec<Alias>>, AdminResponse> {
+    identity.require(AdminScope::Read)?;
+
+    state.store.aliases().map(Json).map_err(internal_error)
+}
+
+async fn get_alias(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(name): Path<String>,
+) -> Result<Json<Alias>, AdminResponse> {
+    identity.require(AdminScope::Read)?;
+
+    match state.store.alias(&name) {
+        Ok(Some(alias)) => Ok(Json(alias)),
+        Ok(None) => Err(AdminResponse::new(
+            StatusCode::NOT_FOUND,
+            format!("Alias '{name}' does not exist"),
+        )),
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+/// Creates or replaces an alias.
+async fn put_alias(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(name): Path<String>,
+    Json(target): Json<AliasTarget>,
+) -> Result<(StatusCode, Json<Alias>), AdminResponse> {
+    identity.require(AdminScope::Alias)?;
+
+    aliases::validate_name(&name)
+        .and_then(|_| target.validate())
+        .map_err(|err| AdminResponse::new(StatusCode::UNPROCESSABLE_ENTITY, format!("{err:#}")))?;
+
+    let (alias, created) = state
+        .store
+        .put_alias(&name, target)
+        .map_err(internal_error)?;
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(alias)))
+}
+
+async fn delete_alias(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(name): Path<String>,
+) -> Result<StatusCode, AdminResponse> {
+    identity.require(AdminScope::Alias)?;
+
+    match state.store.delete_alias(&name) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(AdminResponse::new(
+            StatusCode::NOT_FOUND,
+            format!("Alias '{name}' does not exist"),
+        )),
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum BlockSource {
+    /// Listed in the config file, can only be removed there.
+    Config,
+    /// Added through the admin API.
+    Admin,
+}
+
+#[derive(Serialize)]
+struct ListedBlock {
+    kind: BlockKind,
+    id: u64,
+    reason: Option<String>,
+    source: BlockSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    created_at: Option<DateTime<Utc>>,
+}
+
+async fn list_blocks(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<Vec<ListedBlock>>, AdminResponse> {
+    identity.require(AdminScope::Read)?;
+
+    let runtime = state.runtime.load();
+    let config = &runtime.blocklist;
+    let configured = [
+        (BlockKind::Project, &config.projects),
+        (BlockKind::File, &config.files),
+        (BlockKind::Author, &config.authors),
+    ]
+    .into_iter()
+    .flat_map(|(kind, entries)| {
+        entries.iter().map(move |entry| ListedBlock {
+            kind,
+            id: entry.id,
+            reason: entry.reason.clone(),
+            source: BlockSource::Config,
+            created_at: None,
+        })
+    });
+    let stored = state
+        .store
+        .blocks()
+        .map_err(internal_error)?
+        .into_iter()
+        .map(|block| ListedBlock {
+            kind: block.kind,
+            id: block.id,
+            reason: block.reason,
+            source: BlockSource::Admin,
+            created_at: Some(block.created_at),
+        });
+
+    Ok(Json(configured.chain(stored).collect()))
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct BlockRequest {
+    /// Shown on the 451 page.
+    reason: Option<String>,
+}
+
+/// Blocks a project, file or author, or updates the reason of an existing block.
+async fn put_block(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path((kind, id)): Path<(BlockKind, u64)>,
+    body: Option<Json<BlockRequest>>,
+) -> Result<(StatusCode, Json<Block>), AdminResponse> {
+    identity.require(AdminScope::Block)?;
+
+    if id == 0 {
+        return Err(AdminResponse::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "IDs must be greater than 0",
+        ));
+    }
+
+    let reason = body
+        .and_then(|Json(body)| body.reason)
+        .filter(|it| !it.trim().is_empty());
+    let (block, created) = state
+        .store
+        .put_block(kind, id, reason)
+        .map_err(internal_error)?;
+
+    let status = if created {
+        StatusCode::CREATED
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Json(block)))
+}
+
+async fn delete_block(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path((kind, id)): Path<(BlockKind, u64)>,
+) -> Result<StatusCode, AdminResponse> {
+    identity.require(AdminScope::Block)?;
+
+    match state.store.delete_block(kind, id) {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(AdminResponse::new(
+            StatusCode::NOT_FOUND,
+            format!("No {kind} {id} was blocked through the admin API"),
+        )),
+        Err(err) => Err(internal_error(err)),
+    }
+}
+
+async fn cache_summary(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<CacheSummary>, AdminResponse> {
+    identity.require(AdminScope::Read)?;
+
+    Ok(Json(state.curseforge.cache.summary()))
+}
+
+async fn cached_project(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(project_id): Path<u64>,
+) -> Result<Json<EntryInfo<Mod>>, AdminResponse> {
+    identity.require(AdminScope::Read)?;
+
+    state
+        .curseforge
+        .cache
+        .project_info(project_id)
+        .map(Json)
+        .ok_or_else(|| not_cached(format!("Project {project_id}")))
+}
+
+async fn cached_file(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(file_id): Path<u64>,
+) -> Result<Json<EntryInfo<File>>, AdminResponse> {
+    identity.require(AdminScope::Read)?;
+
+    state
+        .curseforge
+        .cache
+        .file_info(file_id)
+        .map(Json)
+        .ok_or_else(|| not_cached(format!("File {file_id}")))
+}
+
+#[derive(Deserialize)]
+struct PurgeQuery {
+    /// Fetch the entry again right after purging it.
+    #[serde(default)]
+    refetch: bool,
+}
+
+#[derive(Serialize)]
+struct PurgeResponse<T> {
+    purged: bool,
+    /// The refetched entry, if requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<EntryInfo<T>>,
+}
+
+#[derive(Serialize)]
+struct PurgeAllResponse {
+    projects: usize,
+    files: usize,
+}
+
+async fn purge_project(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(project_id): Path<u64>,
+    Query(query): Query<PurgeQuery>,
+) -> Result<Json<PurgeResponse<Mod>>, AdminResponse> {
+    identity.require(AdminScope::Cache)?;
+
+    let cache = &state.curseforge.cache;
+    let purged = cache.purge_project(project_id);
+    if query.refetch {
+        mods::get_mod(&state.curseforge, project_id)
+            .await
+            .map_err(upstream_error)?;
+    }
+    persist_cache(&state).await;
+
+    Ok(Json(PurgeResponse {
+        purged,
+        entry: query
+            .refetch
+            .then(|| cache.project_info(project_id))
+            .flatten(),
+    }))
+}
+
+async fn purge_file(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+    Path(file_id): Path<u64>,
+    Query(query): Query<PurgeQuery>,
+) -> Result<Json<PurgeResponse<File>>, AdminResponse> {
+    identity.require(AdminScope::Cache)?;
+
+    let cache = &state.curseforge.cache;
+    let purged = cache.purge_file(file_id);
+    if query.refetch {
+        mods::get_files(&state.curseforge, vec![file_id])
+            .await
+            .map_err(upstream_error)?;
+    }
+    persist_cache(&state).await;
+
+    Ok(Json(PurgeResponse {
+        purged,
+        entry: query.refetch.then(|| cache.file_info(file_id)).flatten(),
+    }))
+}
+
+async fn purge_cache(
+    State(state): State<Arc<AppState>>,
+    Extension(identity): Extension<AdminIdentity>,
+) -> Result<Json<PurgeAllResponse>, AdminResponse> {
+    identity.require(AdminScope::Cache)?;
+
+    let (projects, files) = state.curseforge.cache.purge_all();
+    persist_cache(&state).await;
+
+    Ok(Json(PurgeAllResponse { projects, files }))
+}
+
+/// Writes the cache snapshot right away, so that purged entries don't come back after a crash.
+async fn persist_cache(state: &Arc<AppState>) {
+    let state = state.clone();
+    let result = tokio::task::spawn_blocking(move || state.curseforge.cache.persist()).await;
+    match result {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => tracing::error!("Unable to persist cache: {err:#}"),
+        Err(err) => tracing::error!("Unable to persist cache: {err}"),
+    }
+}
+
+fn not_cached(what: String) -> AdminResponse {
+    AdminResponse::new(StatusCode::NOT_FOUND, format!("{what} is not cached"))
+}
+
+fn upstream_error(err: anyhow::Error) -> AdminResponse {
+    tracing::error!("Unable to refetch purged cache entry: {err:#}");
+    AdminResponse::new(
+        StatusCode::BAD_GATEWAY,
+        format!("Purged, but unable to refetch: {err:#}"),
+    )
+}
+
+fn internal_error(err: anyhow::Error) -> AdminResponse {
+    tracing::error!("Admin request failed: {err:#}");
+    AdminResponse::new(StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error")
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::config::{AdminScope, AdminToken};
+    use crate::web::test::{new_test_server, new_test_server_with};
+    use axum::http::header::LOCATION;
+    use axum_test::TestServer;
+    use reqwest::StatusCode;
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    const FULL_TOKEN: &str = "test-admin-token-full";
+    const ALIAS_TOKEN: &str = "test-admin-token-alias";
+    const READ_TOKEN: &str = "test-admin-token-read";
+
+    async fn new_admin_server() -> anyhow::Result<TestServer> {
+        new_test_server_with(|config| {
+            for (name, token, scope) in [
+                ("full", FULL_TOKEN, AdminScope::Full),
+                ("alias", ALIAS_TOKEN, AdminScope::Alias),
+                ("read", READ_TOKEN, AdminScope::Read),
+            ] {
+                config.admin.tokens.push(AdminToken {
+                    name: name.to_string(),
+                    sha256: hex::encode(Sha256::digest(token)),
+                    scopes: vec![scope],
+                });
+            }
+        })
+        .await
+    }
+
+    async_tests_with_env! {
+        async fn should_hide_admin_api_without_tokens() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server.post("/admin/reload").await;
+            response.assert_status(StatusCode::NOT_FOUND);
+            Ok(())
+        }
+
+        async fn should_require_admin_token() -> anyhow::Result<()> {
+            let server = new_admin_server().await?;
+
+            let response = server.post("/admin/reload").await;
+            response.assert_status(StatusCode::UNAUTHORIZED);
+
+            let response = server
+                .post("/admin/reload")
+                .authorization_bearer("wrong-token")
+                .await;
+            response.assert_status(StatusCode::UNAUTHORIZED);
+
+            let response = server
+                .post("/admin/reload")
+                .authorization_bearer(FULL_TOKEN)
+                .await;
+            response.assert_status(StatusCode::OK);
+            Ok(())
+        }
+
+        async fn should_enforce_scopes() -> anyhow::Result<()> {
+            let server = new_admin_server().await?;
+            let alias = json!({ "type": "project", "project_id": 911456 });
+
+            let response = server
+                .put("/admin/aliases/sparkweave")
+                .authorization_bearer(READ_TOKEN)
+                .json(&alias)
+                .await;
+            response.assert_status(StatusCode::FORBIDDEN);
+
+            let response = server
+                .put("/admin/aliases/sparkweave")
+                .authorization_bearer(ALIAS_TOKEN)
+                .json(&alias)
+                .await;
+            response.assert_status(StatusCode::CREATED);
+
+            let response = server
+                .get("/admin/aliases")
+                .authorization_bearer(READ_TOKEN)
+                .await;
+            response.assert_status(StatusCode::OK);
+
+            let response = server
+                .post("/admin/reload")
+                .authorization_bearer(ALIAS_TOKEN)
+                .await;
+            response.assert_status(StatusCode::FORBIDDEN);
+            Ok(())
+        }
+
+        async fn should_purge_cache() -> anyhow::Result<()> {
+            let server = new_admin_server().await?;
+
+            let response = server
+                .get("/admin/cache/projects/911456")
+                .authorization_bearer(READ_TOKEN)
+                .await;
+            response.assert_status(StatusCode::NOT_FOUND);
+
+            let response = server
+                .delete("/admin/cache/files/6774233")
+                .authorization_bearer(ALIAS_TOKEN)
+                .await;
+            response.assert_status(StatusCode::FORBIDDEN);
+
+            let response = server
+                .delete("/admin/cache/files/6774233")
+                .authorization_bearer(FULL_TOKEN)
+                .await;
+            response.assert_status(StatusCode::OK);
+            response.assert_json(&json!({ "purged": false }));
+
+            let response = server
+                .delete("/admin/cache")
+                .authorization_bearer(FULL_TOKEN)
+                .await;
+            response.assert_json(&json!({ "projects": 0, "files": 0 }));
+            Ok(())
+        }
+
+        async fn should_manage_blocklist() -> anyhow::Result<()> {
+            let server = new_admin_server().await?;
+
+            let response = server
+                .put("/admin/blocklist/project/911456")
+                .authorization_bearer(ALIAS_TOKEN)
+                .await;
+            response.assert_status(StatusCode::FORBIDDEN);
+
+            let response = server
+                .put("/admin/blocklist/project/911456")
+                .authorization_bearer(FULL_TOKEN)
+                .json(&json!({ "reason": "Takedown request" }))
+                .await;
+            response.assert_status(StatusCode::CREATED);
+
+            let response = server.get("/911456").await;
+            response.assert_status(StatusCode::UNAVAILABLE_FOR_LEGAL_REASONS);
+            response.assert_text_contains("Takedown request");
+
+            let response = server
+                .delete("/admin/blocklist/project/911456")
+                .authorization_bearer(FULL_TOKEN)
+                .await;
+            response.assert_status(StatusCode::NO_CONTENT);
+
+            let response = server.get("/911456").await;
+            response.assert_status(StatusCode::SEE_OTHER);
+            Ok(())
+        }
+
+        async fn should_manage_aliases() -> anyhow::Result<()> {
+            let server = new_admin_server().await?;
+
+            let response = server
+                .put("/admin/aliases/sparkweave")
+                .authorization_bearer(ALIAS_TOKEN)
+                .json(&json!({ "type": "project", "project_id": 911456 }))
+                .await;
+            response.assert_status(StatusCode::CREATED);
+
+            let response = server.get("/sparkweave").await;
+            response.assert_status(StatusCode::SEE_OTHER);
+            response.assert_header(LOCATION, "https://curseforge.com/projects/911456");
+
+            let response = server
+                .put("/admin/aliases/123")
+                .authorization_bearer(ALIAS_TOKEN)
+                .json(&json!({ "type": "project", "project_id": 911456 }))
+                .await;
+            response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+
+            let response = server
+                .delete("/admin/aliases/sparkweave")
+                .authorization_bearer(ALIAS_TOKEN)
+                .await;
+            response.assert_status(StatusCode::NO_CONTENT);
+
+            let response = server.get("/sparkweave").await;
+            response.assert_status(StatusCode::NOT_FOUND);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,285 @@
+use crate::blocklist;
+use crate::blocklist::BlockKind;
+use crate::curseforge::mods;
+use crate::curseforge::mods::{File, Mod};
+use crate::hosts::HostSettings;
+use crate::rate_limit::RateLimited;
+use crate::resolve::SharedResolution;
+use crate::web::AppState;
+use crate::web::lookup::{Check, MAX_IDS, check_blocklist, file_url, short_link, upstream_error};
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub(crate) struct ResolveRequest {
+    #[serde(default)]
+    projects: Vec<u64>,
+    #[serde(default)]
+    files: Vec<u64>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct ResolveResponse {
+    projects: Vec<ResolvedProject>,
+    files: Vec<ResolvedFile>,
+}
+
+#[derive(Serialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub(super) enum LinkStatus {
+    Ok,
+    /// The target doesn't exist or isn't served on this host.
+    NotFound,
+    Blocked,
+}
+
+#[derive(Serialize)]
+struct ResolvedProject {
+    id: u64,
+    status: LinkStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    short_link: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    slug: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(super) struct ResolvedFile {
+    id: u64,
+    status: LinkStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    short_link: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    /// Only set if the author allows third party downloads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    project_id: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+}
+
+/// Resolves many projects and files at once, with bulk Curseforge API calls for those not cached.
+///
+/// Every API call it may make costs a rate limit token, charged before the first one.
+#[tracing::instrument(skip_all, fields(projects = req.projects.len(), files = req.files.len()))]
+pub(crate) async fn resolve(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Extension(resolution): Extension<SharedResolution>,
+    limited: Option<Extension<RateLimited>>,
+    Json(req): Json<ResolveRequest>,
+) -> Result<Response, (StatusCode, String)> {
+    let project_ids = dedup(req.projects);
+    let file_ids = dedup(req.files);
+    if project_ids.len() + file_ids.len() > MAX_IDS {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {MAX_IDS} IDs can be resolved at once"),
+        ));
+    }
+
+    // the projects of the files are only known after looking them up, at most one per file
+    let requests = mods::get_files_requests(&state.curseforge, &file_ids)
+        + mods::get_mods_requests(&state.curseforge, &project_ids, file_ids.len());
+    if let Err(rejected) = state.rate_limit.charge(limited.as_deref(), requests) {
+        return Ok(rejected.into_response());
+    }
+    let files = mods::get_files(&state.curseforge, file_ids.clone())
+        .await
+        .map_err(|err| upstream_error(&resolution, "batch resolution", err))?;
+    let lookups: Vec<u64> = project_ids
+        .iter()
+        .copied()
+        .chain(files.values().map(|file| file.project_id))
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let projects = mods::get_mods(&state.curseforge, lookups)
+        .await
+        .map_err(|err| upstream_error(&resolution, "batch resolution", err))?;
+
+    let mut response = ResolveResponse {
+        projects: Vec::with_capacity(project_ids.len()),
+        files: Vec::with_capacity(file_ids.len()),
+    };
+
+    for id in project_ids {
+        let short_link = short_link(&host, &id.to_string());
+        let project = projects
+            .get(&id)
+            .filter(|project| host.allows_game(project.game_id));
+        let check = match project {
+            Some(project) => {
+                check_blocklist(&resolution, blocklist::check_project(&state, project))?
+            }
+            None => Check::NotFound,
+        };
+
+        response.projects.push(match (check, project) {
+            (Check::Ok, Some(project)) => ResolvedProject {
+                id,
+                status: LinkStatus::Ok,
+                reason: None,
+                short_link,
+                url: Some(project.links.website_url.clone()),
+                name: Some(project.name.clone()),
+                slug: Some(project.slug.clone()),
+            },
+            (check, _) => {
+                let (status, reason) = unresolved(check);
+                ResolvedProject {
+                    id,
+                    status,
+                    reason,
+                    short_link,
+                    url: None,
+                    name: None,
+                    slug: None,
+                }
+            }
+        });
+    }
+
+    for id in file_ids {
+        response.files.push(resolve_file(
+            &state,
+            &host,
+            &resolution,
+            id,
+            &files,
+            &projects,
+        )?);
+    }
+
+    Ok(Json(response).into_response())
+}
+
+/// Resolves a file from the results of the bulk lookups.
+pub(super) fn resolve_file(
+    state: &AppState,
+    host: &HostSettings,
+    resolution: &SharedResolution,
+    id: u64,
+    files: &HashMap<u64, File>,
+    projects: &HashMap<u64, Mod>,
+) -> Result<ResolvedFile, (StatusCode, String)> {
+    let short_link = short_link(host, &format!("f/{id}"));
+    let found = files.get(&id).and_then(|file| {
+        let project = projects.get(&file.project_id)?;
+        host.allows_game(project.game_id).then_some((file, project))
+    });
+    let check = match found {
+        Some((_, project)) => {
+            match check_blocklist(resolution, blocklist::check(state, BlockKind::File, id))? {
+                Check::Ok => check_blocklist(resolution, blocklist::check_project(state, project))?,
+                other => other,
+            }
+        }
+        None => Check::NotFound,
+    };
+
+    Ok(match (check, found) {
+        (Check::Ok, Some((file, project))) => ResolvedFile {
+            id,
+            status: LinkStatus::Ok,
+            reason: None,
+            short_link,
+            url: Some(file_url(project, id)),
+            download_url: file.download_url.clone(),
+            project_id: Some(file.project_id),
+            file_name: Some(file.file_name.clone()),
+            display_name: file.display_name.clone(),
+        },
+        (check, _) => {
+            let (status, reason) = unresolved(check);
+            ResolvedFile {
+                id,
+                status,
+                reason,
+                short_link,
+                url: None,
+                download_url: None,
+                project_id: None,
+                file_name: None,
+                display_name: None,
+            }
+        }
+    })
+}
+
+/// Removes duplicate IDs, keeping the order they were requested in.
+fn dedup(ids: Vec<u64>) -> Vec<u64> {
+    let mut seen = HashSet::with_capacity(ids.len());
+    ids.into_iter().filter(|id| seen.insert(*id)).collect()
+}
+
+fn unresolved(check: Check) -> (LinkStatus, Option<String>) {
+    match check {
+        Check::Blocked(reason) => (LinkStatus::Blocked, reason),
+        Check::Ok | Check::NotFound => (LinkStatus::NotFound, None),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::batch::dedup;
+    use crate::web::test::new_test_server;
+    use reqwest::StatusCode;
+    use serde_json::json;
+
+    #[test]
+    fn should_keep_order_when_deduplicating() {
+        assert_eq!(dedup(vec![3, 1, 3, 2, 1]), vec![3, 1, 2]);
+    }
+
+    async_tests_with_env! {
+        async fn should_resolve_batch() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server
+                .post("/api/v1/resolve")
+                .json(&json!({ "projects": [911456, 911456], "files": [6774233] }))
+                .await;
+            response.assert_status_ok();
+            let body: serde_json::Value = response.json();
+            assert_eq!(body["projects"].as_array().map(Vec::len), Some(1));
+            assert_eq!(body["projects"][0]["status"], "ok");
+            assert!(
+                body["projects"][0]["short_link"]
+                    .as_str()
+                    .is_some_and(|it| it.ends_with("/911456"))
+            );
+            assert_eq!(body["files"][0]["status"], "ok");
+            assert_eq!(body["files"][0]["project_id"], 911456);
+            Ok(())
+        }
+
+        async fn should_limit_batch_size() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let projects: Vec<u64> = (1..=1001).collect();
+            let response = server
+                .post("/api/v1/resolve")
+                .json(&json!({ "projects": projects }))
+                .await;
+            response.assert_status(StatusCode::PAYLOAD_TOO_LARGE);
+            Ok(())
+        }
+    }
+}
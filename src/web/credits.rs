@@ -0,0 +1,355 @@
+use crate::blocklist;
+use crate::curseforge::mods;
+use crate::curseforge::mods::Mod;
+use crate::hosts::HostSettings;
+use crate::rate_limit::RateLimited;
+use crate::resolve::SharedResolution;
+use crate::util::{escape_html, escape_markdown, from_json_value};
+use crate::web::AppState;
+use crate::web::lookup::{Check, MAX_IDS, check_blocklist, short_link, upstream_error};
+use crate::web::manifest;
+use crate::web::manifest::ZIP_MAGIC;
+use axum::Extension;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{Html, IntoResponse, Response};
+use bytes::Bytes;
+use serde::Deserialize;
+use serde_json::Value;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Heading of projects without a primary category when grouping by category.
+const OTHER_CATEGORY: &str = "Other";
+
+#[derive(Deserialize)]
+struct ProjectList {
+    projects: Vec<u64>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CreditsFormat {
+    #[default]
+    Markdown,
+    Bbcode,
+    Html,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CreditsSort {
+    #[default]
+    Name,
+    /// Most downloaded first.
+    Downloads,
+    /// In the order the projects were listed in.
+    Input,
+}
+
+#[derive(Deserialize, Default, Clone, Copy, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum CreditsGrouping {
+    #[default]
+    None,
+    /// By the primary category of each project.
+    Category,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct CreditsQuery {
+    #[serde(default)]
+    format: CreditsFormat,
+    #[serde(default)]
+    sort: CreditsSort,
+    #[serde(default)]
+    group: CreditsGrouping,
+    /// Heading of the whole list.
+    title: Option<String>,
+}
+
+struct Credit {
+    name: String,
+    link: String,
+    authors: Vec<String>,
+    category: String,
+    download_count: usize,
+}
+
+/// Renders a credits list for a set of projects, given as `{"projects": [...]}`, a modpack
+/// `manifest.json` or a modpack zip.
+///
+/// Projects that don't exist, aren't served on this host or are blocked are left out. Every
+/// Curseforge API call costs a rate limit token.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn credits(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Extension(resolution): Extension<SharedResolution>,
+    limited: Option<Extension<RateLimited>>,
+    Query(query): Query<CreditsQuery>,
+    body: Bytes,
+) -> Result<Response, (StatusCode, String)> {
+    let project_ids = parse(&body).map_err(|err| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Invalid project list: {err:#}"),
+        )
+    })?;
+    if project_ids.len() > MAX_IDS {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {MAX_IDS} projects can be credited at once"),
+        ));
+    }
+
+    let requests = mods::get_mods_requests(&state.curseforge, &project_ids, 0);
+    if let Err(rejected) = state.rate_limit.charge(limited.as_deref(), requests) {
+        return Ok(rejected.into_response());
+    }
+    let projects = mods::get_mods(&state.curseforge, project_ids.clone())
+        .await
+        .map_err(|err| upstream_error(&resolution, "credits listing", err))?;
+
+    let mut credits = Vec::with_capacity(project_ids.len());
+    for project_id in &project_ids {
+        let Some(project) = projects
+            .get(project_id)
+            .filter(|project| host.allows_game(project.game_id))
+        else {
+            continue;
+        };
+        if let Check::Ok = check_blocklist(&resolution, blocklist::check_project(&state, project))?
+        {
+            credits.push(credit(&host, project));
+        }
+    }
+
+    match query.sort {
+        CreditsSort::Name => credits.sort_by_key(|it| it.name.to_lowercase()),
+        CreditsSort::Downloads => credits.sort_by_key(|it| Reverse(it.download_count)),
+        CreditsSort::Input => {}
+    }
+
+    let mut groups: BTreeMap<&str, Vec<&Credit>> = BTreeMap::new();
+    for credit in &credits {
+        let group = match query.group {
+            CreditsGrouping::None => "",
+            CreditsGrouping::Category => credit.category.as_str(),
+        };
+        groups.entry(group).or_default().push(credit);
+    }
+    let title = query.title.as_deref().unwrap_or("Credits");
+
+    Ok(match query.format {
+        CreditsFormat::Markdown => (
+            [(CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            render_markdown(title, &groups),
+        )
+            .into_response(),
+        CreditsFormat::Bbcode => (
+            [(CONTENT_TYPE, "text/plain; charset=utf-8")],
+            render_bbcode(title, &groups),
+        )
+            .into_response(),
+        CreditsFormat::Html => Html(render_html(title, &groups)).into_response(),
+    })
+}
+
+/// Reads the project IDs to credit, without duplicates.
+fn parse(body: &[u8]) -> anyhow::Result<Vec<u64>> {
+    let project_ids = if body.starts_with(ZIP_MAGIC) {
+        manifest_projects(manifest::parse(body)?)
+    } else {
+        let value: Value = serde_json::from_slice(body)?;
+        if value.get("files").is_some() {
+            manifest_projects(manifest::parse(body)?)
+        } else {
+            from_json_value::<ProjectList>(&value)?.projects
+        }
+    };
+
+    let mut seen = HashSet::with_capacity(project_ids.len());
+    Ok(project_ids
+        .into_iter()
+        .filter(|id| seen.insert(*id))
+        .collect())
+}
+
+fn manifest_projects(manifest: manifest::Manifest) -> Vec<u64> {
+    manifest.files.iter().map(|it| it.project_id).collect()
+}
+
+fn credit(host: &HostSettings, project: &Mod) -> Credit {
+    Credit {
+        name: project.name.clone(),
+        link: short_link(host, &project.id.to_string()),
+        authors: project.authors.iter().map(|it| it.name.clone()).collect(),
+        category: project
+            .primary_category()
+            .map_or_else(|| OTHER_CATEGORY.to_string(), |it| it.name.clone()),
+        download_count: project.download_count,
+    }
+}
+
+fn render_markdown(title: &str, groups: &BTreeMap<&str, Vec<&Credit>>) -> String {
+    let mut markdown = format!("# {}\n", escape_markdown(title));
+    for (group, credits) in groups {
+        if !group.is_empty() {
+            let _ = write!(markdown, "\n## {}\n", escape_markdown(group));
+        }
+        markdown.push('\n');
+        for credit in credits {
+            let _ = write!(
+                markdown,
+                "- [{name}]({link})",
+                name = escape_markdown(&credit.name),
+                link = credit.link,
+            );
+            if !credit.authors.is_empty() {
+                let authors: Vec<_> = credit
+                    .authors
+                    .iter()
+                    .map(|it| escape_markdown(it))
+                    .collect();
+                let _ = write!(markdown, " by {}", authors.join(", "));
+            }
+            markdown.push('\n');
+        }
+    }
+    markdown
+}
+
+/// BBCode has no escape sequences, so brackets in names are replaced to keep them from being read
+/// as tags.
+fn escape_bbcode(value: &str) -> String {
+    value.replace('[', "(").replace(']', ")")
+}
+
+fn render_bbcode(title: &str, groups: &BTreeMap<&str, Vec<&Credit>>) -> String {
+    let mut bbcode = format!("[size=150][b]{}[/b][/size]\n", escape_bbcode(title));
+    for (group, credits) in groups {
+        if !group.is_empty() {
+            let _ = write!(bbcode, "\n[b]{}[/b]\n", escape_bbcode(group));
+        }
+        bbcode.push_str("[list]\n");
+        for credit in credits {
+            let _ = write!(
+                bbcode,
+                "[*][url={link}]{name}[/url]",
+                link = credit.link,
+                name = escape_bbcode(&credit.name),
+            );
+            if !credit.authors.is_empty() {
+                let _ = write!(bbcode, " by {}", escape_bbcode(&credit.authors.join(", ")));
+            }
+            bbcode.push('\n');
+        }
+        bbcode.push_str("[/list]\n");
+    }
+    bbcode
+}
+
+fn render_html(title: &str, groups: &BTreeMap<&str, Vec<&Credit>>) -> String {
+    let title = escape_html(title);
+    let mut sections = String::new();
+    for (group, credits) in groups {
+        if !group.is_empty() {
+            let _ = writeln!(sections, "<h2>{}</h2>", escape_html(group));
+        }
+        sections.push_str("<ul>\n");
+        for credit in credits {
+            let _ = write!(
+                sections,
+                "<li><a href=\"{link}\">{name}</a>",
+                link = escape_html(&credit.link),
+                name = escape_html(&credit.name),
+            );
+            if !credit.authors.is_empty() {
+                let _ = write!(sections, " by {}", escape_html(&credit.authors.join(", ")));
+            }
+            sections.push_str("</li>\n");
+        }
+        sections.push_str("</ul>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{sections}</body>
+</html>
+"
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::credits::{Credit, parse, render_bbcode, render_markdown};
+    use crate::web::test::new_test_server;
+    use reqwest::StatusCode;
+    use std::collections::BTreeMap;
+
+    fn credit(name: &str, authors: &[&str]) -> Credit {
+        Credit {
+            name: name.to_string(),
+            link: "https://mods.cf/1".to_string(),
+            authors: authors.iter().map(ToString::to_string).collect(),
+            category: "Library".to_string(),
+            download_count: 0,
+        }
+    }
+
+    #[test]
+    fn should_read_project_lists_and_manifests() -> anyhow::Result<()> {
+        assert_eq!(parse(br#"{ "projects": [3, 1, 3] }"#)?, vec![3, 1]);
+        assert_eq!(
+            parse(br#"{ "files": [{ "projectID": 5, "fileID": 6 }, { "projectID": 7, "fileID": 8 }] }"#)?,
+            vec![5, 7]
+        );
+        assert!(parse(br#"{ "projects": ["a"] }"#).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn should_render_groups() {
+        let library = credit("Up [Lib]", &["Up", "Mods"]);
+        let other = credit("*Fancy*", &[]);
+        let groups = BTreeMap::from([("Library", vec![&library]), ("Other", vec![&other])]);
+
+        let markdown = render_markdown("Credits", &groups);
+        assert!(
+            markdown.contains("## Library\n\n- [Up \\[Lib\\]](https://mods.cf/1) by Up, Mods\n")
+        );
+        assert!(markdown.contains("- [\\*Fancy\\*](https://mods.cf/1)\n"));
+
+        let bbcode = render_bbcode("Credits", &groups);
+        assert!(bbcode.contains("[*][url=https://mods.cf/1]Up (Lib)[/url] by Up, Mods\n"));
+    }
+
+    async_tests_with_env! {
+        async fn should_reject_invalid_project_list() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server
+                .post("/api/v1/credits")
+                .bytes(r#"{ "projects": 911456 }"#.into())
+                .await;
+            response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+            Ok(())
+        }
+    }
+}
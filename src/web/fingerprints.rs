@@ -0,0 +1,114 @@
+use crate::curseforge::{fingerprints, mods};
+use crate::hosts::HostSettings;
+use crate::rate_limit::RateLimited;
+use crate::resolve;
+use crate::resolve::SharedResolution;
+use crate::web::AppState;
+use crate::web::batch::{ResolvedFile, resolve_file};
+use crate::web::lookup::upstream_error;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Serialize)]
+pub(crate) struct FingerprintResponse {
+    fingerprint: u32,
+    /// The file with this fingerprint, if Curseforge knows one.
+    file: Option<ResolvedFile>,
+}
+
+/// Redirects to the file with a Curseforge fingerprint.
+#[tracing::instrument(skip(state, host, resolution))]
+pub(crate) async fn file_by_fingerprint(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Extension(resolution): Extension<SharedResolution>,
+    Path(fingerprint): Path<u32>,
+) -> impl IntoResponse {
+    match resolve::fingerprint(&state, &host, &resolution, fingerprint).await {
+        Ok(resolution) => resolution.into_response(),
+        Err(err) => {
+            tracing::error!("Error during lookup for fingerprint {fingerprint}: {err:#}");
+            resolve::record_error(&resolution, &err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Fingerprints an uploaded file, such as a mod jar, and looks up the matching Curseforge file.
+///
+/// Every Curseforge API call costs a rate limit token.
+#[tracing::instrument(skip_all, fields(size = body.len()))]
+pub(crate) async fn identify(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Extension(resolution): Extension<SharedResolution>,
+    limited: Option<Extension<RateLimited>>,
+    body: Bytes,
+) -> Result<Response, (StatusCode, String)> {
+    if body.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "The file is empty".to_string()));
+    }
+
+    let fingerprint = tokio::task::spawn_blocking(move || fingerprints::fingerprint(&body))
+        .await
+        .map_err(|err| (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()))?;
+
+    // one fingerprint lookup, and at most one for the project of the matching file
+    let requests = 1 + mods::get_mods_requests(&state.curseforge, &[], 1);
+    if let Err(rejected) = state.rate_limit.charge(limited.as_deref(), requests) {
+        return Ok(rejected.into_response());
+    }
+    let matches = fingerprints::get_fingerprint_matches(&state.curseforge, vec![fingerprint])
+        .await
+        .map_err(|err| upstream_error(&resolution, "fingerprint lookup", err))?;
+    let Some(matched) = matches.get(&fingerprint) else {
+        return Ok(Json(FingerprintResponse {
+            fingerprint,
+            file: None,
+        })
+        .into_response());
+    };
+
+    let projects = mods::get_mods(&state.curseforge, vec![matched.project_id])
+        .await
+        .map_err(|err| upstream_error(&resolution, "fingerprint lookup", err))?;
+    let files = HashMap::from([(matched.id, matched.clone())]);
+    let file = resolve_file(&state, &host, &resolution, matched.id, &files, &projects)?;
+
+    Ok(Json(FingerprintResponse {
+        fingerprint,
+        file: Some(file),
+    })
+    .into_response())
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::test::new_test_server;
+    use reqwest::StatusCode;
+
+    async_tests_with_env! {
+        async fn should_reject_empty_upload() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server.post("/api/v1/fingerprints").await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+            Ok(())
+        }
+
+        async fn should_reject_invalid_fingerprint() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server.get("/fp/not-a-number").await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,244 @@
+use crate::curseforge::mods::{File, FileHashAlgorithm};
+use crate::hosts::HostSettings;
+use crate::resolve;
+use crate::resolve::SharedResolution;
+use crate::web::AppState;
+use crate::web::lookup::upstream_error;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use bytes::Bytes;
+use md5::Md5;
+use serde::{Deserialize, Serialize};
+use sha1::{Digest, Sha1};
+use std::sync::Arc;
+
+#[derive(Serialize)]
+pub(crate) struct FileHashes {
+    file_id: u64,
+    file_name: String,
+    size: usize,
+    /// Hex digests as recorded by Curseforge.
+    sha1: Option<String>,
+    md5: Option<String>,
+}
+
+impl FileHashes {
+    fn new(file: &File) -> Self {
+        let hash = |matches: fn(&FileHashAlgorithm) -> bool| {
+            file.hashes
+                .iter()
+                .find(|it| matches(&it.algorithm))
+                .map(|it| it.value.to_lowercase())
+        };
+
+        FileHashes {
+            file_id: file.id,
+            file_name: file.file_name.clone(),
+            size: file.size,
+            sha1: hash(|it| matches!(it, FileHashAlgorithm::SHA1)),
+            md5: hash(|it| matches!(it, FileHashAlgorithm::MD5)),
+        }
+    }
+}
+
+/// Digests to verify instead of an uploaded file.
+#[derive(Deserialize)]
+pub(crate) struct VerifyQuery {
+    sha1: Option<String>,
+    md5: Option<String>,
+}
+
+#[derive(Serialize)]
+pub(crate) struct Verification {
+    file_id: u64,
+    file_name: String,
+    /// Whether at least one digest could be compared and all compared digests match.
+    verified: bool,
+    checks: Vec<Check>,
+}
+
+#[derive(Serialize)]
+struct Check {
+    algorithm: &'static str,
+    /// The digest recorded by Curseforge, if any.
+    expected: Option<String>,
+    actual: String,
+    matches: bool,
+}
+
+/// Lists the hashes Curseforge recorded for a file.
+#[tracing::instrument(skip(state, host, resolution))]
+pub(crate) async fn hashes(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Extension(resolution): Extension<SharedResolution>,
+    Path(file_id): Path<u64>,
+) -> Response {
+    match lookup(&state, &host, &resolution, file_id).await {
+        Ok(file) => Json(FileHashes::new(&file)).into_response(),
+        Err(response) => response,
+    }
+}
+
+/// Checks an uploaded file, or the digests given as query parameters, against the hashes
+/// Curseforge recorded for a file.
+#[tracing::instrument(skip(state, host, resolution, query, body))]
+pub(crate) async fn verify(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Extension(resolution): Extension<SharedResolution>,
+    Path(file_id): Path<u64>,
+    Query(query): Query<VerifyQuery>,
+    body: Bytes,
+) -> Response {
+    let digests = if query.sha1.is_some() || query.md5.is_some() {
+        let sha1 = query.sha1.map(|it| parse_digest(&it, 20)).transpose();
+        let md5 = query.md5.map(|it| parse_digest(&it, 16)).transpose();
+        match (sha1, md5) {
+            (Ok(sha1), Ok(md5)) => Digests { sha1, md5 },
+            _ => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "Digests must be hex encoded, with 40 characters for SHA-1 and 32 for MD5",
+                )
+                    .into_response();
+            }
+        }
+    } else if body.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Upload the file or pass its sha1 or md5 digest",
+        )
+            .into_response();
+    } else {
+        match tokio::task::spawn_blocking(move || Digests::of(&body)).await {
+            Ok(digests) => digests,
+            Err(err) => {
+                return (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()).into_response();
+            }
+        }
+    };
+
+    let file = match lookup(&state, &host, &resolution, file_id).await {
+        Ok(file) => file,
+        Err(response) => return response,
+    };
+    let recorded = FileHashes::new(&file);
+
+    let mut checks = Vec::new();
+    if let Some(actual) = digests.sha1 {
+        checks.push(Check::new("sha1", recorded.sha1, actual));
+    }
+    if let Some(actual) = digests.md5 {
+        checks.push(Check::new("md5", recorded.md5, actual));
+    }
+    let compared: Vec<_> = checks.iter().filter(|it| it.expected.is_some()).collect();
+
+    Json(Verification {
+        file_id,
+        file_name: file.file_name,
+        verified: !compared.is_empty() && compared.iter().all(|it| it.matches),
+        checks,
+    })
+    .into_response()
+}
+
+struct Digests {
+    sha1: Option<String>,
+    md5: Option<String>,
+}
+
+impl Digests {
+    fn of(content: &[u8]) -> Self {
+        Digests {
+            sha1: Some(hex::encode(Sha1::digest(content))),
+            md5: Some(hex::encode(Md5::digest(content))),
+        }
+    }
+}
+
+impl Check {
+    fn new(algorithm: &'static str, expected: Option<String>, actual: String) -> Self {
+        Check {
+            algorithm,
+            matches: expected.as_deref() == Some(actual.as_str()),
+            expected,
+            actual,
+        }
+    }
+}
+
+/// Normalizes a hex digest of `len` bytes to lowercase.
+fn parse_digest(value: &str, len: usize) -> anyhow::Result<String> {
+    let bytes = hex::decode(value.trim())?;
+    anyhow::ensure!(
+        bytes.len() == len,
+        "expected {len} bytes, got {}",
+        bytes.len()
+    );
+    Ok(hex::encode(bytes))
+}
+
+/// Looks up a file that may be served on this host.
+async fn lookup(
+    state: &AppState,
+    host: &HostSettings,
+    resolution: &SharedResolution,
+    file_id: u64,
+) -> Result<File, Response> {
+    match resolve::find_file(state, host, resolution, file_id).await {
+        Ok(Ok((_, file))) => Ok(file),
+        Ok(Err(unavailable)) => Err(unavailable.into_response()),
+        Err(err) => Err(upstream_error(resolution, "hash lookup", err).into_response()),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::hashes::{Digests, parse_digest};
+    use crate::web::test::new_test_server;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn should_digest_content() {
+        let digests = Digests::of(b"abc");
+        assert_eq!(
+            digests.sha1.as_deref(),
+            Some("a9993e364706816aba3e25717850c26c9cd0d89d")
+        );
+        assert_eq!(
+            digests.md5.as_deref(),
+            Some("900150983cd24fb0d6963f7d28e17f72")
+        );
+    }
+
+    #[test]
+    fn should_parse_digests() {
+        assert_eq!(
+            parse_digest("900150983CD24FB0D6963F7D28E17F72", 16)
+                .ok()
+                .as_deref(),
+            Some("900150983cd24fb0d6963f7d28e17f72")
+        );
+        assert!(parse_digest("900150983cd24fb0d6963f7d28e17f72", 20).is_err());
+        assert!(parse_digest("not hex", 16).is_err());
+    }
+
+    async_tests_with_env! {
+        async fn should_require_file_or_digest() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server.post("/api/v1/files/6774233/verify").await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+
+            let response = server
+                .post("/api/v1/files/6774233/verify?sha1=abc")
+                .await;
+            response.assert_status(StatusCode::BAD_REQUEST);
+            Ok(())
+        }
+    }
+}
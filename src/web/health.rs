@@ -0,0 +1,49 @@
+use crate::util::HealthResponse;
+use crate::web::AppState;
+use axum::extract::State;
+use axum::http::StatusCode;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+/// Reports whether the process is up, without checking any dependencies.
+pub(crate) async fn live() -> HealthResponse {
+    HealthResponse::from(StatusCode::OK)
+}
+
+/// Reports whether the service is able to serve traffic.
+pub(crate) async fn ready(State(state): State<Arc<AppState>>) -> HealthResponse {
+    let checks = BTreeMap::from([
+        ("curseforge", state.curseforge.probe().await),
+        ("cache", state.curseforge.cache.health_check()),
+        ("store", state.store.health_check()),
+        ("analytics", state.runtime.load().analytics.health_check()),
+    ]);
+
+    HealthResponse::from_checks(checks)
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::test::new_test_server;
+    use reqwest::StatusCode;
+
+    async_tests_with_env! {
+        async fn should_be_live() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server.get("/health/live").await;
+            response.assert_status(StatusCode::OK);
+            response.assert_json(&serde_json::json!({ "status": 200, "message": "OK" }));
+            Ok(())
+        }
+
+        async fn should_be_ready() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server.get("/health/ready").await;
+            response.assert_status(StatusCode::OK);
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,72 @@
+use crate::blocklist::Blocked;
+use crate::config::BlockResponse;
+use crate::curseforge::mods::Mod;
+use crate::hosts::HostSettings;
+use crate::resolve;
+use crate::resolve::{ErrorKind, ResolutionError, SharedResolution};
+use axum::http::StatusCode;
+
+/// Most project and file IDs resolved in one request.
+pub(super) const MAX_IDS: usize = 1000;
+
+/// Outcome of checking a found target against the blocklist.
+pub(super) enum Check {
+    Ok,
+    NotFound,
+    Blocked(Option<String>),
+}
+
+impl From<Option<Blocked>> for Check {
+    fn from(blocked: Option<Blocked>) -> Self {
+        match blocked {
+            None => Check::Ok,
+            Some(blocked) if blocked.response == BlockResponse::NotFound => Check::NotFound,
+            Some(blocked) => Check::Blocked(blocked.reason),
+        }
+    }
+}
+
+pub(super) fn short_link(host: &HostSettings, path: &str) -> String {
+    host.public_url
+        .join(path)
+        .map_or_else(|_| format!("/{path}"), |it| it.to_string())
+}
+
+pub(super) fn file_url(project: &Mod, file_id: u64) -> String {
+    format!("{}/files/{file_id}", project.links.website_url)
+}
+
+pub(super) fn check_blocklist(
+    resolution: &SharedResolution,
+    result: anyhow::Result<Option<Blocked>>,
+) -> Result<Check, (StatusCode, String)> {
+    result.map(Check::from).map_err(|err| {
+        tracing::error!("Unable to check blocklist: {err:#}");
+        resolve::record_error(resolution, &err);
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to check blocklist".to_string(),
+        )
+    })
+}
+
+/// Logs a failed lookup during `context`, e.g. "batch resolution", and records it, so that it is
+/// reported like failures on redirect routes.
+pub(super) fn upstream_error(
+    resolution: &SharedResolution,
+    context: &str,
+    err: anyhow::Error,
+) -> (StatusCode, String) {
+    tracing::error!("Error during {context}: {err:#}");
+    resolve::record_error(resolution, &err);
+    match ResolutionError::new(&err).kind {
+        ErrorKind::Internal => (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "Unable to look up projects and files".to_string(),
+        ),
+        _ => (
+            StatusCode::BAD_GATEWAY,
+            "Unable to look up projects and files on Curseforge".to_string(),
+        ),
+    }
+}
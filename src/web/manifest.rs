@@ -0,0 +1,512 @@
+use crate::blocklist;
+use crate::blocklist::BlockKind;
+use crate::curseforge::mods;
+use crate::curseforge::mods::{File, FileStatus, Mod, ModStatus};
+use crate::hosts::HostSettings;
+use crate::rate_limit::RateLimited;
+use crate::resolve::SharedResolution;
+use crate::util::{escape_html, escape_markdown};
+use crate::web::AppState;
+use crate::web::lookup::{Check, MAX_IDS, check_blocklist, file_url, short_link, upstream_error};
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{Html, IntoResponse, Response};
+use axum::{Extension, Json};
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::io::{Cursor, Read};
+use std::sync::Arc;
+
+/// Largest modpack zip accepted, overrides included.
+pub(crate) const MAX_UPLOAD_SIZE: usize = 64 * 1024 * 1024;
+
+/// Largest `manifest.json` read from a modpack zip.
+const MAX_MANIFEST_SIZE: u64 = 8 * 1024 * 1024;
+
+pub(super) const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+/// The parts of a Curseforge modpack `manifest.json` needed to list its mods.
+#[derive(Deserialize)]
+pub(super) struct Manifest {
+    name: Option<String>,
+    version: Option<String>,
+    author: Option<String>,
+    minecraft: Option<ManifestMinecraft>,
+    pub files: Vec<ManifestFile>,
+}
+
+#[derive(Deserialize)]
+struct ManifestMinecraft {
+    version: Option<String>,
+    #[serde(rename = "modLoaders", default)]
+    mod_loaders: Vec<ManifestModLoader>,
+}
+
+#[derive(Deserialize)]
+struct ManifestModLoader {
+    id: String,
+}
+
+#[derive(Deserialize)]
+pub(super) struct ManifestFile {
+    #[serde(rename = "projectID")]
+    pub project_id: u64,
+    #[serde(rename = "fileID")]
+    file_id: u64,
+    #[serde(default = "crate::util::default_true")]
+    required: bool,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ModListFormat {
+    #[default]
+    Json,
+    Markdown,
+    Html,
+}
+
+#[derive(Deserialize)]
+pub(crate) struct ManifestQuery {
+    #[serde(default)]
+    format: ModListFormat,
+}
+
+#[derive(Serialize)]
+struct ModList {
+    name: Option<String>,
+    version: Option<String>,
+    author: Option<String>,
+    game_version: Option<String>,
+    mod_loaders: Vec<String>,
+    /// Number of mods whose file can't be downloaded as listed.
+    flagged: usize,
+    mods: Vec<ModListEntry>,
+}
+
+#[derive(Serialize, Clone, Copy, Eq, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+enum EntryStatus {
+    Ok,
+    /// The project or file doesn't exist or isn't served on this host.
+    NotFound,
+    Blocked,
+    /// The file or project has been taken down, archived or rejected.
+    Unavailable,
+    Deleted,
+    Malware,
+}
+
+impl EntryStatus {
+    fn label(self) -> &'static str {
+        match self {
+            EntryStatus::Ok => "OK",
+            EntryStatus::NotFound => "Not found",
+            EntryStatus::Blocked => "Blocked",
+            EntryStatus::Unavailable => "Unavailable",
+            EntryStatus::Deleted => "Deleted",
+            EntryStatus::Malware => "Malware detected",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ModListEntry {
+    project_id: u64,
+    file_id: u64,
+    required: bool,
+    status: EntryStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+    project_link: String,
+    file_link: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    download_url: Option<String>,
+}
+
+/// Lists the mods of a modpack, from its `manifest.json` or the whole modpack zip.
+///
+/// Every Curseforge API call costs a rate limit token.
+#[tracing::instrument(skip_all)]
+pub(crate) async fn mod_list(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Extension(resolution): Extension<SharedResolution>,
+    limited: Option<Extension<RateLimited>>,
+    Query(query): Query<ManifestQuery>,
+    body: Bytes,
+) -> Result<Response, (StatusCode, String)> {
+    let manifest = parse(&body).map_err(|err| {
+        (
+            StatusCode::UNPROCESSABLE_ENTITY,
+            format!("Invalid modpack: {err:#}"),
+        )
+    })?;
+    if manifest.files.len() > MAX_IDS {
+        return Err((
+            StatusCode::PAYLOAD_TOO_LARGE,
+            format!("At most {MAX_IDS} mods can be listed at once"),
+        ));
+    }
+
+    let file_ids: Vec<u64> = manifest
+        .files
+        .iter()
+        .map(|it| it.file_id)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let project_ids: Vec<u64> = manifest
+        .files
+        .iter()
+        .map(|it| it.project_id)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let requests = mods::get_files_requests(&state.curseforge, &file_ids)
+        + mods::get_mods_requests(&state.curseforge, &project_ids, 0);
+    if let Err(rejected) = state.rate_limit.charge(limited.as_deref(), requests) {
+        return Ok(rejected.into_response());
+    }
+
+    let files = mods::get_files(&state.curseforge, file_ids)
+        .await
+        .map_err(|err| upstream_error(&resolution, "modpack listing", err))?;
+    let projects = mods::get_mods(&state.curseforge, project_ids)
+        .await
+        .map_err(|err| upstream_error(&resolution, "modpack listing", err))?;
+
+    let mut entries = Vec::with_capacity(manifest.files.len());
+    for listed in &manifest.files {
+        let project = projects
+            .get(&listed.project_id)
+            .filter(|project| host.allows_game(project.game_id));
+        let file = files
+            .get(&listed.file_id)
+            .filter(|file| file.project_id == listed.project_id);
+        entries.push(entry(&state, &host, &resolution, listed, project, file)?);
+    }
+
+    let minecraft = manifest.minecraft.as_ref();
+    let list = ModList {
+        name: manifest.name,
+        version: manifest.version,
+        author: manifest.author,
+        game_version: minecraft.and_then(|it| it.version.clone()),
+        mod_loaders: minecraft
+            .map(|it| it.mod_loaders.iter().map(|it| it.id.clone()).collect())
+            .unwrap_or_default(),
+        flagged: entries
+            .iter()
+            .filter(|it| it.status != EntryStatus::Ok)
+            .count(),
+        mods: entries,
+    };
+
+    Ok(match query.format {
+        ModListFormat::Json => Json(list).into_response(),
+        ModListFormat::Markdown => (
+            [(CONTENT_TYPE, "text/markdown; charset=utf-8")],
+            render_markdown(&list),
+        )
+            .into_response(),
+        ModListFormat::Html => Html(render_html(&list)).into_response(),
+    })
+}
+
+/// Reads a manifest, either as is or from the root of a modpack zip.
+pub(super) fn parse(body: &[u8]) -> anyhow::Result<Manifest> {
+    if !body.starts_with(ZIP_MAGIC) {
+        return decode(body);
+    }
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(body))?;
+    let entry = archive.by_name("manifest.json")?;
+    let mut manifest = Vec::new();
+    entry.take(MAX_MANIFEST_SIZE).read_to_end(&mut manifest)?;
+    decode(&manifest)
+}
+
+fn decode(manifest: &[u8]) -> anyhow::Result<Manifest> {
+    let deserializer = &mut serde_json::Deserializer::from_slice(manifest);
+    Ok(serde_path_to_error::deserialize(deserializer)?)
+}
+
+fn entry(
+    state: &AppState,
+    host: &HostSettings,
+    resolution: &SharedResolution,
+    listed: &ManifestFile,
+    project: Option<&Mod>,
+    file: Option<&File>,
+) -> Result<ModListEntry, (StatusCode, String)> {
+    let mut entry = ModListEntry {
+        project_id: listed.project_id,
+        file_id: listed.file_id,
+        required: listed.required,
+        status: EntryStatus::NotFound,
+        reason: None,
+        name: None,
+        project_link: short_link(host, &listed.project_id.to_string()),
+        file_link: short_link(host, &format!("f/{}", listed.file_id)),
+        url: None,
+        file_name: None,
+        download_url: None,
+    };
+    let (Some(project), Some(file)) = (project, file) else {
+        return Ok(entry);
+    };
+
+    let check = match check_blocklist(
+        resolution,
+        blocklist::check(state, BlockKind::File, file.id),
+    )? {
+        Check::Ok => check_blocklist(resolution, blocklist::check_project(state, project))?,
+        other => other,
+    };
+    match check {
+        Check::Ok => {}
+        Check::NotFound => return Ok(entry),
+        Check::Blocked(reason) => {
+            entry.status = EntryStatus::Blocked;
+            entry.reason = reason;
+            return Ok(entry);
+        }
+    }
+
+    entry.status = status(project, file);
+    entry.name = Some(project.name.clone());
+    entry.url = Some(file_url(project, file.id));
+    entry.file_name = Some(file.file_name.clone());
+    entry.download_url = file.download_url.clone();
+    Ok(entry)
+}
+
+fn status(project: &Mod, file: &File) -> EntryStatus {
+    if matches!(file.status, FileStatus::MalwareDetected) {
+        EntryStatus::Malware
+    } else if matches!(file.status, FileStatus::Deleted)
+        || matches!(project.status, ModStatus::Deleted)
+    {
+        EntryStatus::Deleted
+    } else if !file.is_available
+        || !project.is_available
+        || matches!(file.status, FileStatus::Rejected | FileStatus::Archived)
+    {
+        EntryStatus::Unavailable
+    } else {
+        EntryStatus::Ok
+    }
+}
+
+fn title(list: &ModList) -> String {
+    let name = list.name.as_deref().unwrap_or("Modpack");
+    match &list.version {
+        Some(version) => format!("{name} {version}"),
+        None => name.to_string(),
+    }
+}
+
+/// Describes the author and the game version the modpack is made for, if known.
+fn subtitle(list: &ModList) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(author) = &list.author {
+        parts.push(format!("By {author}."));
+    }
+    if let Some(version) = &list.game_version {
+        let mut game = format!("Minecraft {version}");
+        if !list.mod_loaders.is_empty() {
+            let _ = write!(game, " with {}", list.mod_loaders.join(", "));
+        }
+        parts.push(format!("{game}."));
+    }
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+fn summary(list: &ModList) -> String {
+    match list.flagged {
+        0 => format!("{} mods, all available.", list.mods.len()),
+        flagged => format!(
+            "{} mods, {flagged} of them can't be downloaded as listed.",
+            list.mods.len()
+        ),
+    }
+}
+
+fn render_markdown(list: &ModList) -> String {
+    let mut markdown = format!("# {}\n\n", escape_markdown(&title(list)));
+    if let Some(subtitle) = subtitle(list) {
+        let _ = writeln!(markdown, "{}\n", escape_markdown(&subtitle));
+    }
+    let _ = writeln!(markdown, "{}\n", summary(list));
+    markdown.push_str("| Mod | File | Status |\n| --- | --- | --- |\n");
+
+    for entry in &list.mods {
+        let name = entry
+            .name
+            .as_deref()
+            .map_or_else(|| format!("Project {}", entry.project_id), escape_markdown);
+        let file = entry
+            .file_name
+            .as_deref()
+            .map_or_else(|| format!("File {}", entry.file_id), escape_markdown);
+        let mut status = entry.status.label().to_string();
+        if let Some(reason) = &entry.reason {
+            let _ = write!(status, ": {}", escape_markdown(reason));
+        }
+        let optional = if entry.required { "" } else { " (optional)" };
+        let _ = writeln!(
+            markdown,
+            "| [{name}]({project_link}){optional} | [{file}]({file_link}) | {status} |",
+            project_link = entry.project_link,
+            file_link = entry.file_link,
+        );
+    }
+
+    markdown
+}
+
+fn render_html(list: &ModList) -> String {
+    let title = escape_html(&title(list));
+    let subtitle = subtitle(list)
+        .map(|it| format!("<p>{}</p>\n", escape_html(&it)))
+        .unwrap_or_default();
+
+    let mut rows = String::new();
+    for entry in &list.mods {
+        let name = entry
+            .name
+            .as_deref()
+            .map_or_else(|| format!("Project {}", entry.project_id), escape_html);
+        let file = entry
+            .file_name
+            .as_deref()
+            .map_or_else(|| format!("File {}", entry.file_id), escape_html);
+        let mut status = entry.status.label().to_string();
+        if let Some(reason) = &entry.reason {
+            let _ = write!(status, ": {}", escape_html(reason));
+        }
+        let optional = if entry.required {
+            ""
+        } else {
+            " <small>(optional)</small>"
+        };
+        let class = if entry.status == EntryStatus::Ok {
+            ""
+        } else {
+            " class=\"flagged\""
+        };
+        let _ = writeln!(
+            rows,
+            "<tr{class}><td><a href=\"{project_link}\">{name}</a>{optional}</td>\
+            <td><a href=\"{file_link}\">{file}</a></td><td>{status}</td></tr>",
+            project_link = escape_html(&entry.project_link),
+            file_link = escape_html(&entry.file_link),
+        );
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>{title}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 60rem; margin: 2rem auto; padding: 0 1rem; }}
+table {{ border-collapse: collapse; width: 100%; }}
+td, th {{ padding: 0.25rem 0.5rem; text-align: left; }}
+.flagged {{ background: #fde8e1; }}
+</style>
+</head>
+<body>
+<h1>{title}</h1>
+{subtitle}<p>{summary}</p>
+<table>
+<tr><th>Mod</th><th>File</th><th>Status</th></tr>
+{rows}</table>
+</body>
+</html>
+",
+        summary = summary(list),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::manifest::parse;
+    use crate::web::test::new_test_server;
+    use reqwest::StatusCode;
+    use std::io::{Cursor, Write};
+    use zip::write::SimpleFileOptions;
+
+    const MANIFEST: &str = r#"{
+        "minecraft": { "version": "1.21.1", "modLoaders": [{ "id": "neoforge-21.1.1", "primary": true }] },
+        "manifestType": "minecraftModpack",
+        "manifestVersion": 1,
+        "name": "Example Pack",
+        "version": "1.0.0",
+        "author": "Up-Mods",
+        "files": [
+            { "projectID": 911456, "fileID": 6774233, "required": true },
+            { "projectID": 238222, "fileID": 5101366, "required": false }
+        ],
+        "overrides": "overrides"
+    }"#;
+
+    #[test]
+    fn should_read_manifest_from_zip() -> anyhow::Result<()> {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file(
+            "overrides/config/example.toml",
+            SimpleFileOptions::default(),
+        )?;
+        zip.write_all(b"enabled = true")?;
+        zip.start_file("manifest.json", SimpleFileOptions::default())?;
+        zip.write_all(MANIFEST.as_bytes())?;
+        let zip = zip.finish()?.into_inner();
+
+        let manifest = parse(&zip)?;
+        assert_eq!(manifest.name.as_deref(), Some("Example Pack"));
+        assert_eq!(manifest.files.len(), 2);
+        assert!(!manifest.files[1].required);
+        Ok(())
+    }
+
+    async_tests_with_env! {
+        async fn should_reject_invalid_manifest() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server
+                .post("/api/v1/manifest")
+                .bytes(r#"{ "files": [{ "projectID": "abc" }] }"#.into())
+                .await;
+            response.assert_status(StatusCode::UNPROCESSABLE_ENTITY);
+            response.assert_text_contains("files[0].projectID");
+            Ok(())
+        }
+
+        async fn should_list_mods_as_markdown() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server
+                .post("/api/v1/manifest?format=markdown")
+                .bytes(MANIFEST.into())
+                .await;
+            response.assert_status_ok();
+            response.assert_text_contains("# Example Pack 1.0.0");
+            response.assert_text_contains("/f/6774233)");
+            Ok(())
+        }
+    }
+}
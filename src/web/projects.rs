@@ -1,9 +1,49 @@
-use axum::extract::Path;
-use axum::response::{IntoResponse, Redirect};
+use crate::hosts::HostSettings;
+use crate::resolve;
+use crate::resolve::SharedResolution;
+use crate::web::AppState;
+use axum::Extension;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use std::sync::Arc;
 
-pub(crate) async fn project_by_id(Path(project_id): Path<u64>) -> impl IntoResponse {
-    let target = format!("https://curseforge.com/projects/{project_id}");
-    Redirect::to(target.as_str())
+/// Redirects numeric keys to the project with that ID and everything else to the alias of that name.
+#[tracing::instrument(skip(state, host, resolution))]
+pub(crate) async fn project_or_alias(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Extension(resolution): Extension<SharedResolution>,
+    Path(key): Path<String>,
+) -> impl IntoResponse {
+    if let Ok(project_id) = key.parse::<u64>() {
+        return match resolve::project(&state, &host, &resolution, project_id).await {
+            Ok(resolution) => resolution.into_response(),
+            Err(err) => {
+                tracing::error!("Error during lookup for project {project_id}: {err:#}");
+                resolve::record_error(&resolution, &err);
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        };
+    }
+
+    let alias = match state.store.alias(&key) {
+        Ok(Some(alias)) => alias,
+        Ok(None) => return StatusCode::NOT_FOUND.into_response(),
+        Err(err) => {
+            tracing::error!("Unable to read alias {key}: {err:#}");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    match resolve::alias(&state, &host, &resolution, &key, &alias.target).await {
+        Ok(resolution) => resolution.into_response(),
+        Err(err) => {
+            tracing::error!("Error during lookup for alias {key}: {err:#}");
+            resolve::record_error(&resolution, &err);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
 }
 
 #[cfg(test)]
@@ -22,5 +62,13 @@ mod test {
             response.assert_header(LOCATION, "https://curseforge.com/projects/911456");
             Ok(())
         }
+
+        async fn should_not_find_unknown_alias() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server.get("/does-not-exist").await;
+            response.assert_status(StatusCode::NOT_FOUND);
+            Ok(())
+        }
     }
 }
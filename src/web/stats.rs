@@ -0,0 +1,236 @@
+use crate::hosts::HostSettings;
+use crate::stats;
+use crate::stats::{LinkStats, StatsTarget};
+use crate::util::escape_html;
+use crate::web::AppState;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::sync::Arc;
+
+const DEFAULT_DAYS: u32 = 30;
+const MAX_DAYS: u32 = 365;
+
+#[derive(Deserialize)]
+pub(crate) struct StatsQuery {
+    /// Number of days to report, including today.
+    days: Option<u32>,
+}
+
+impl StatsQuery {
+    fn days(&self) -> u32 {
+        self.days.unwrap_or(DEFAULT_DAYS).clamp(1, MAX_DAYS)
+    }
+}
+
+pub(crate) async fn project_stats(
+    State(state): State<Arc<AppState>>,
+    Path(project_id): Path<u64>,
+    Query(query): Query<StatsQuery>,
+) -> Response {
+    json_stats(&state, StatsTarget::Project { project_id }, &query)
+}
+
+pub(crate) async fn file_stats(
+    State(state): State<Arc<AppState>>,
+    Path(file_id): Path<u64>,
+    Query(query): Query<StatsQuery>,
+) -> Response {
+    json_stats(&state, StatsTarget::File { file_id }, &query)
+}
+
+pub(crate) async fn alias_stats(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Response {
+    json_stats(&state, StatsTarget::Alias { name }, &query)
+}
+
+/// Renders the click statistics of `/{key}`, which is either a project ID or an alias.
+pub(crate) async fn project_or_alias_page(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Path(key): Path<String>,
+    Query(query): Query<StatsQuery>,
+) -> Response {
+    let target = match key.parse::<u64>() {
+        Ok(project_id) => StatsTarget::Project { project_id },
+        Err(_) => StatsTarget::Alias { name: key.clone() },
+    };
+    html_stats(&state, &host, target, &format!("/{key}"), &query)
+}
+
+pub(crate) async fn file_page(
+    State(state): State<Arc<AppState>>,
+    Extension(host): Extension<Arc<HostSettings>>,
+    Path(file_id): Path<u64>,
+    Query(query): Query<StatsQuery>,
+) -> Response {
+    let target = StatsTarget::File { file_id };
+    html_stats(&state, &host, target, &format!("/f/{file_id}"), &query)
+}
+
+fn json_stats(state: &AppState, target: StatsTarget, query: &StatsQuery) -> Response {
+    match collect(state, target, query) {
+        Ok(stats) => Json(stats).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+fn html_stats(
+    state: &AppState,
+    host: &HostSettings,
+    target: StatsTarget,
+    link: &str,
+    query: &StatsQuery,
+) -> Response {
+    match collect(state, target, query) {
+        Ok(stats) => Html(render(&stats, host, link)).into_response(),
+        Err(status) => status.into_response(),
+    }
+}
+
+fn collect(
+    state: &AppState,
+    target: StatsTarget,
+    query: &StatsQuery,
+) -> Result<LinkStats, StatusCode> {
+    if !state.clicks.is_enabled() {
+        return Err(StatusCode::NOT_FOUND);
+    }
+    if let StatsTarget::Alias { name } = &target {
+        match state.store.alias(name) {
+            Ok(Some(_)) => {}
+            Ok(None) => return Err(StatusCode::NOT_FOUND),
+            Err(err) => {
+                tracing::error!("Unable to read alias {name}: {err:#}");
+                return Err(StatusCode::INTERNAL_SERVER_ERROR);
+            }
+        }
+    }
+
+    stats::link_stats(state, target, query.days()).map_err(|err| {
+        tracing::error!("Unable to read click statistics: {err:#}");
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+fn render(stats: &LinkStats, host: &HostSettings, link: &str) -> String {
+    let url = host
+        .public_url
+        .join(link)
+        .map_or_else(|_| link.to_string(), |it| it.to_string());
+    let url = escape_html(&url);
+    let max = stats
+        .days
+        .iter()
+        .map(|it| it.clicks)
+        .max()
+        .unwrap_or(0)
+        .max(1);
+
+    let mut days = String::new();
+    for day in stats.days.iter().rev() {
+        let width = day.clicks * 100 / max;
+        let _ = writeln!(
+            days,
+            "<tr><td>{date}</td><td class=\"num\">{clicks}</td><td class=\"num\">{bots}</td>\
+            <td class=\"bar\"><span style=\"width: {width}%\"></span></td></tr>",
+            date = day.date,
+            clicks = day.clicks,
+            bots = day.bots,
+        );
+    }
+
+    let mut referrers: Vec<_> = stats.referrers.iter().collect();
+    referrers.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    let mut referrer_rows = String::new();
+    for (referrer, clicks) in referrers {
+        let _ = writeln!(
+            referrer_rows,
+            "<tr><td>{referrer}</td><td class=\"num\">{clicks}</td></tr>",
+            referrer = escape_html(referrer),
+        );
+    }
+
+    format!(
+        "<!DOCTYPE html>
+<html lang=\"en\">
+<head>
+<meta charset=\"utf-8\">
+<meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">
+<title>Clicks on {url}</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 48rem; margin: 2rem auto; padding: 0 1rem; }}
+table {{ border-collapse: collapse; width: 100%; margin-bottom: 2rem; }}
+td, th {{ padding: 0.25rem 0.5rem; text-align: left; }}
+.num {{ text-align: right; }}
+.bar {{ width: 50%; }}
+.bar span {{ display: block; height: 0.75rem; background: #f16436; }}
+</style>
+</head>
+<body>
+<h1>Clicks on <a href=\"{url}\">{url}</a></h1>
+<p>{clicks} clicks from {from} to {to}, {bots} of them by bots. Days are in UTC.</p>
+<table>
+<tr><th>Day</th><th class=\"num\">Clicks</th><th class=\"num\">Bots</th><th></th></tr>
+{days}</table>
+<h2>Referrers</h2>
+<table>
+<tr><th>Domain</th><th class=\"num\">Clicks</th></tr>
+{referrer_rows}</table>
+</body>
+</html>
+",
+        clicks = stats.clicks,
+        from = stats.from,
+        to = stats.to,
+        bots = stats.bots,
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use crate::async_tests_with_env;
+    use crate::web::test::new_test_server;
+    use reqwest::StatusCode;
+    use serde_json::Value;
+
+    async_tests_with_env! {
+        async fn should_count_clicks() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            for _ in 0..2 {
+                let response = server
+                    .get("/911456")
+                    .add_header("referer", "https://discord.com/channels/1")
+                    .await;
+                response.assert_status(StatusCode::SEE_OTHER);
+            }
+
+            let response = server.get("/api/v1/stats/projects/911456?days=7").await;
+            response.assert_status_ok();
+            let stats: Value = response.json();
+            assert_eq!(stats["clicks"], 2);
+            assert_eq!(stats["days"].as_array().map(Vec::len), Some(7));
+            assert_eq!(stats["referrers"]["discord.com"], 2);
+
+            let response = server.get("/911456/stats").await;
+            response.assert_status_ok();
+            response.assert_text_contains("2 clicks");
+            Ok(())
+        }
+
+        async fn should_not_find_stats_of_unknown_alias() -> anyhow::Result<()> {
+            let server = new_test_server().await?;
+
+            let response = server.get("/api/v1/stats/aliases/does-not-exist").await;
+            response.assert_status(StatusCode::NOT_FOUND);
+            Ok(())
+        }
+    }
+}